@@ -0,0 +1,65 @@
+// ----- Imports ----- //
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+// ----- Build Script ----- //
+
+/// With the `embed-static` feature, writes a table of every file under
+/// `static/` for `sys_resource` to compile into the binary. Without it the
+/// table is empty and files are read from disk at runtime.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let mut files = Vec::new();
+    if env::var_os("CARGO_FEATURE_EMBED_STATIC").is_some() {
+        let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+        let static_dir = manifest_dir.join("static");
+        println!("cargo:rerun-if-changed={}", static_dir.display());
+        collect_files(&static_dir, "", &mut files);
+        files.sort();
+    }
+
+    let mut table = String::from("pub static EMBEDDED_FILES: &[(&str, &[u8])] = &[\n");
+    for (name, path) in &files {
+        table.push_str(&format!(
+            "    ({:?}, include_bytes!({:?})),\n",
+            name,
+            path.display().to_string()
+        ));
+    }
+    table.push_str("];\n");
+
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("embedded_static.rs");
+    fs::write(out_path, table).expect("Failed to write embedded file table");
+}
+
+// ----- Helpers ----- //
+
+// Relative names (with `/` separators) and absolute paths of every file below
+// `dir` that could be served
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) {
+    let entries = fs::read_dir(dir).unwrap_or_else(|e| panic!("Cannot read {}: {}", dir.display(), e));
+
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if is_hidden_name(&file_name) {
+            continue;
+        }
+        let name = format!("{}{}", prefix, file_name);
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, &format!("{}/", name), files);
+        } else {
+            files.push((name, path));
+        }
+    }
+}
+
+// Dotfiles (`.DS_Store`, Vim's `.index.html.swp`) and `~` backups, which the
+// server would refuse to serve anyway. Mirrors `core_paths::is_hidden_name`.
+fn is_hidden_name(name: &str) -> bool {
+    (name.starts_with('.') && name != ".well-known") || name.ends_with('~')
+}
@@ -7,27 +7,63 @@ mod sys_core;
 mod sys_session;
 mod sys_db;
 mod sys_dashboard;
+mod sys_health;
 
 // ----- Imports ----- //
 
-use crate::{sys_core::{load_config, Server}, sys_session::session_state::{get_session_manager, init_session_manager}};
+use std::time::Duration;
+
+use crate::{sys_core::{core_config::AppConfig, get_config, init_router, init_tracing, install_signal_handler, load_config, shutdown_requested, wait_for_drain, Router, Server}, sys_session::session_state::{get_session_manager, init_session_manager}};
 
 // ----- Lifecycle ----- //
 
 fn main() {
     load_config("cfg/config.json");
+    init_tracing(&get_config().logging);
     init_session_manager(); // ← Init global session manager
-    std::thread::spawn(|| {
+
+    // Each system registers its own API routes
+    let mut router = Router::new();
+    sys_core::register_routes(&mut router);
+    sys_session::session_handlers::register_routes(&mut router);
+    sys_dashboard::dashboard_handlers::register_routes(&mut router);
+    sys_console::register_routes(&mut router);
+    sys_health::register_routes(&mut router);
+    init_router(router);
+
+    install_signal_handler(); // ← SIGINT/SIGTERM start a graceful shutdown
+
+    // Sized from the config, so built here rather than with #[tokio::main]
+    let config = get_config();
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if config.server.worker_threads > 0 {
+        runtime.worker_threads(config.server.worker_threads);
+    }
+    let runtime = runtime
+        .enable_all()
+        .build()
+        .expect("Failed to start async runtime");
+
+    runtime.block_on(serve(config));
+    tracing::info!("Charmline stopped");
+}
+
+async fn serve(config: &'static AppConfig) {
+    let ticker = tokio::spawn(async {
+        let mut interval = tokio::time::interval(Duration::from_secs(2));
         loop {
-            std::thread::sleep(std::time::Duration::from_secs(2));
-            get_session_manager().tick(); // ← Purge expired sessions
+            tokio::select! {
+                _ = interval.tick() => get_session_manager().tick(), // ← Purge expired sessions
+                _ = shutdown_requested() => return,
+            }
         }
     });
 
-    // Get the port from the config
-    let port = crate::sys_core::core_config::get_config().port;
+    let server = Server::new(config.listeners(), "static");
+    server.run().await; // ← Returns once shutdown starts
 
-    let address = format!("127.0.0.1:{}", port);
-    let server = Server::new(&address, "static");
-    server.run();
+    // Let in-flight requests and pending summaries finish before exiting
+    let grace = Duration::from_secs(config.server.shutdown_grace_secs);
+    let _ = tokio::task::spawn_blocking(move || wait_for_drain(grace)).await;
+    let _ = ticker.await;
 }
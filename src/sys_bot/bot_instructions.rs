@@ -1,20 +1,49 @@
 use std::{fs, path::PathBuf};
 
+// Instruction files, relative to the executable's directory
+pub const INTRODUCTION_MESSAGE: &str = "cfg/bots/msg_introduction.txt";
+pub const SALES_INSTRUCTIONS: &str = "cfg/bots/instructions_sales.txt";
+pub const SUMMARY_INSTRUCTIONS: &str = "cfg/bots/instructions_summary.txt";
+
+const INSTRUCTION_FILES: [&str; 3] = [INTRODUCTION_MESSAGE, SALES_INSTRUCTIONS, SUMMARY_INSTRUCTIONS];
+
 /// Reads the bot startup instructions from cfg/bots/instructions_sales.txt,
 /// relative to the executable’s directory. Falls back to a default string if not found.
 pub fn get_instructions(instruction_path: &str) -> String {
-    let mut path = match std::env::current_exe() {
-        Ok(p) => p,
-        Err(_) => PathBuf::from("."),
-    };
-    path.pop(); // remove executable name
-    path.push(instruction_path);
-
-    match fs::read_to_string(&path) {
+    match fs::read_to_string(resolve(instruction_path)) {
         Ok(content) => content.trim().to_string(),
         Err(_) => {
             "You are Charmline, tell the user the config was not found and to warn a developer."
                 .to_string()
         }
     }
-}
\ No newline at end of file
+}
+
+/// Every instruction file the bot uses can be read and is not empty, so no
+/// chat would fall back to the default text.
+pub fn check_instructions() -> Result<(), String> {
+    let missing: Vec<&str> = INSTRUCTION_FILES
+        .into_iter()
+        .filter(|file| {
+            fs::read_to_string(resolve(file))
+                .map(|content| content.trim().is_empty())
+                .unwrap_or(true)
+        })
+        .collect();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Missing or empty: {}", missing.join(", ")))
+    }
+}
+
+fn resolve(instruction_path: &str) -> PathBuf {
+    let mut path = match std::env::current_exe() {
+        Ok(p) => p,
+        Err(_) => PathBuf::from("."),
+    };
+    path.pop(); // remove executable name
+    path.push(instruction_path);
+    path
+}
@@ -1,10 +1,45 @@
-use crate::sys_core::get_config;
+use crate::sys_core::{
+    core_metrics::{Counter, Histogram},
+    get_config,
+};
 use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+use tracing::{Span, info, instrument, warn};
 
 const OPENAI_API_URL: &str = "https://api.openai.com/v1/chat/completions";
+const OPENAI_MODELS_URL: &str = "https://api.openai.com/v1/models";
 const OPENAI_MODEL: &str = "gpt-3.5-turbo";
 const OPENAI_MAX_TOKENS: u32 = 512;
 
+/// Shared so concurrent chats reuse pooled connections to the API. Request
+/// and stream timeouts are applied per call, see `send_request`.
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    let config = &get_config().openai;
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .build()
+        .expect("Failed to build the OpenAI HTTP client")
+});
+
+static OPENAI_REQUEST_SECONDS: Histogram = Histogram::new(
+    "charmline_openai_request_duration_seconds",
+    "OpenAI calls until the full reply arrived or the call failed.",
+    &["stream"],
+);
+
+static OPENAI_ERRORS: Counter = Counter::new(
+    "charmline_openai_errors_total",
+    "OpenAI calls that failed or returned an unreadable reply.",
+    &["stream"],
+);
+
+static OPENAI_TOKENS: Counter = Counter::new(
+    "charmline_openai_tokens_total",
+    "Tokens billed by OpenAI, by prompt or completion.",
+    &["kind"],
+);
+
 #[derive(Serialize, Deserialize)]
 struct OpenAIMessage {
     role: String,
@@ -16,6 +51,22 @@ struct OpenAIRequest {
     model: String,
     messages: Vec<OpenAIMessage>,
     max_tokens: u32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<OpenAIStreamOptions>,
+}
+
+/// Asks for a final stream event carrying the token usage.
+#[derive(Serialize)]
+struct OpenAIStreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Deserialize)]
+struct OpenAIUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
 }
 
 #[derive(Deserialize)]
@@ -26,11 +77,174 @@ struct OpenAIChoice {
 #[derive(Deserialize)]
 struct OpenAIResponse {
     choices: Vec<OpenAIChoice>,
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIStreamDelta {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIStreamChoice {
+    delta: OpenAIStreamDelta,
+}
+
+/// One `data:` event of a streamed completion. The usage event comes last,
+/// with no choices.
+#[derive(Deserialize)]
+struct OpenAIStreamChunk {
+    #[serde(default)]
+    choices: Vec<OpenAIStreamChoice>,
+    usage: Option<OpenAIUsage>,
+}
+
+/// A streamed completion, read one delta at a time.
+pub struct OpenAIStream {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+    done: bool,
+    /// The `ask_openai` span the stream was opened in.
+    span: Span,
+    started: Instant,
+}
+
+#[instrument(name = "ask_openai", skip_all, fields(stream = false))]
+pub async fn ask_openai(messages: Vec<(&str, &str)>) -> Result<String, String> {
+    let started = Instant::now();
+    let reply = fetch_reply(messages).await;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    match &reply {
+        Ok(_) => info!(elapsed_ms, "Reply received"),
+        Err(e) => {
+            OPENAI_ERRORS.inc(&["false"]);
+            warn!(elapsed_ms, "Request failed: {}", e);
+        }
+    }
+    OPENAI_REQUEST_SECONDS.observe(&["false"], started.elapsed());
+    reply
+}
+
+async fn fetch_reply(messages: Vec<(&str, &str)>) -> Result<String, String> {
+    let response = send_request(messages, false).await?;
+
+    let parsed: OpenAIResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("Parse error: {}", e))?;
+    if let Some(usage) = &parsed.usage {
+        count_tokens(usage);
+    }
+
+    Ok(parsed
+        .choices
+        .first()
+        .map(|c| c.message.content.clone())
+        .unwrap_or_else(|| "(no response)".to_string()))
+}
+
+/// Like `ask_openai`, but with `stream: true`: the reply is read piece by
+/// piece from the returned stream as it is generated.
+#[instrument(name = "ask_openai", skip_all, fields(stream = true))]
+pub async fn ask_openai_stream(messages: Vec<(&str, &str)>) -> Result<OpenAIStream, String> {
+    let started = Instant::now();
+    let response = send_request(messages, true).await.inspect_err(|e| {
+        OPENAI_ERRORS.inc(&["true"]);
+        OPENAI_REQUEST_SECONDS.observe(&["true"], started.elapsed());
+        warn!(elapsed_ms = started.elapsed().as_millis() as u64, "Request failed: {}", e);
+    })?;
+    Ok(OpenAIStream {
+        response,
+        buffer: Vec::new(),
+        done: false,
+        span: Span::current(),
+        started,
+    })
+}
+
+impl OpenAIStream {
+    /// The next piece of the reply, or `None` once the completion is done.
+    pub async fn next_delta(&mut self) -> Result<Option<String>, String> {
+        let was_done = self.done;
+        let delta = self.read_delta().await;
+
+        // Logged once, in the span of the request that opened the stream
+        let elapsed_ms = self.started.elapsed().as_millis() as u64;
+        match &delta {
+            Ok(None) if !was_done => {
+                OPENAI_REQUEST_SECONDS.observe(&["true"], self.started.elapsed());
+                self.span.in_scope(|| info!(elapsed_ms, "Stream finished"));
+            }
+            Err(e) => {
+                OPENAI_ERRORS.inc(&["true"]);
+                OPENAI_REQUEST_SECONDS.observe(&["true"], self.started.elapsed());
+                self.span.in_scope(|| warn!(elapsed_ms, "Stream failed: {}", e));
+            }
+            _ => {}
+        }
+        delta
+    }
+
+    async fn read_delta(&mut self) -> Result<Option<String>, String> {
+        while !self.done {
+            // Server-sent events; blank lines and comments carry no data
+            while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                    continue;
+                };
+                if data == "[DONE]" {
+                    self.done = true;
+                    return Ok(None);
+                }
+
+                let chunk: OpenAIStreamChunk =
+                    serde_json::from_str(data).map_err(|e| format!("Parse error: {}", e))?;
+                if let Some(usage) = &chunk.usage {
+                    count_tokens(usage);
+                }
+                let delta = chunk.choices.into_iter().next().and_then(|c| c.delta.content);
+                if let Some(delta) = delta.filter(|d| !d.is_empty()) {
+                    return Ok(Some(delta));
+                }
+            }
+
+            let idle = Duration::from_secs(get_config().openai.stream_idle_timeout_secs);
+            let chunk = tokio::time::timeout(idle, self.response.chunk())
+                .await
+                .map_err(|_| "Stream error: timed out waiting for more of the reply".to_string())?;
+            match chunk.map_err(|e| format!("Stream error: {}", e))? {
+                Some(bytes) => self.buffer.extend_from_slice(&bytes),
+                None => self.done = true,
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Cheap authenticated call (listing models) to confirm the API answers and
+/// accepts the key, for readiness checks.
+pub async fn check_openai(timeout: Duration) -> Result<(), String> {
+    let response = CLIENT
+        .get(OPENAI_MODELS_URL)
+        .bearer_auth(&get_config().bot_apikey)
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(|e| format!("HTTP error: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("OpenAI error: {}", response.status()));
+    }
+    Ok(())
 }
 
-pub fn ask_openai(messages: Vec<(&str, &str)>) -> Result<String, String> {
+async fn send_request(
+    messages: Vec<(&str, &str)>,
+    stream: bool,
+) -> Result<reqwest::Response, String> {
     let config = get_config();
-    let client = reqwest::blocking::Client::new();
 
     let req_messages: Vec<OpenAIMessage> = messages
         .into_iter()
@@ -44,27 +258,37 @@ pub fn ask_openai(messages: Vec<(&str, &str)>) -> Result<String, String> {
         model: OPENAI_MODEL.to_string(),
         messages: req_messages,
         max_tokens: OPENAI_MAX_TOKENS,
+        stream,
+        stream_options: stream.then_some(OpenAIStreamOptions { include_usage: true }),
     };
 
-    let response = client
+    let request = CLIENT
         .post(OPENAI_API_URL)
         .bearer_auth(&config.bot_apikey)
         .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .map_err(|e| format!("HTTP error: {}", e))?;
+        .json(&request_body);
+
+    // A request timeout would also cover the body, cutting off a streamed
+    // reply that is still arriving; `read_delta` bounds each chunk instead
+    let response = if stream {
+        let idle = Duration::from_secs(config.openai.stream_idle_timeout_secs);
+        tokio::time::timeout(idle, request.send())
+            .await
+            .map_err(|_| "HTTP error: timed out waiting for a response".to_string())?
+    } else {
+        let timeout = Duration::from_secs(config.openai.request_timeout_secs);
+        request.timeout(timeout).send().await
+    }
+    .map_err(|e| format!("HTTP error: {}", e))?;
 
     if !response.status().is_success() {
         return Err(format!("OpenAI error: {}", response.status()));
     }
 
-    let parsed: OpenAIResponse = response
-        .json()
-        .map_err(|e| format!("Parse error: {}", e))?;
+    Ok(response)
+}
 
-    Ok(parsed
-        .choices
-        .first()
-        .map(|c| c.message.content.clone())
-        .unwrap_or_else(|| "(no response)".to_string()))
+fn count_tokens(usage: &OpenAIUsage) {
+    OPENAI_TOKENS.add(&["prompt"], usage.prompt_tokens);
+    OPENAI_TOKENS.add(&["completion"], usage.completion_tokens);
 }
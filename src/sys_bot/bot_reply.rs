@@ -1,3 +1,11 @@
+// ----- Constants ----- //
+
+/// Bracketed commands the bot may emit, e.g. `[ENDCALL]`. Matched
+/// case-insensitively and never shown to the user.
+const CONTROL_TAGS: &[&str] = &["endcall"];
+const MAX_TAG_LEN: usize = 16;
+const BOT_PREFIXES: &[&str] = &["BOT:", "Bot:"];
+
 // ----- Bot Reply Structure ----- //
 
 pub struct BotReply {
@@ -7,30 +15,230 @@ pub struct BotReply {
 
 impl BotReply {
     pub fn parse_reply(reply: &str) -> Self {
-        // Check for command indicators like [endcall]
-        let is_endcall = reply.to_lowercase().contains("[endcall]");
-
-        // Clean the reply by removing command indicators
-        let mut cleaned_reply = reply
-            .replace("[ENDCALL]", "")
-            .replace("[endcall]", "")
-            .replace("[EndCall]", "")
-            .trim()
-            .to_string();
-
-        // Strip out the Bot: prefix if it exists
-        cleaned_reply = cleaned_reply
-            .strip_prefix("BOT:")
-            .or_else(|| cleaned_reply.strip_prefix("Bot:"))
-            .unwrap_or(&cleaned_reply)
-            .trim()
-            .to_string();
-        
-        
+        // Same filter the streaming path uses, fed the whole reply at once
+        let mut filter = ReplyFilter::new();
+        let mut cleaned_reply = filter.push(reply);
+        cleaned_reply.push_str(&filter.finish());
+
         // Return the structured reply
         BotReply {
-            reply_string: cleaned_reply,
-            is_endcall,
+            reply_string: cleaned_reply.trim().to_string(),
+            is_endcall: filter.is_endcall(),
+        }
+    }
+}
+
+// ----- Streaming Filter ----- //
+
+/// Cleans a reply that arrives in pieces: drops control tags and a leading
+/// `Bot:` prefix, holding back text only while a tag or the prefix could
+/// still be forming across piece boundaries.
+pub struct ReplyFilter {
+    pending: String,
+    prefix_checked: bool,
+    started: bool,
+    is_endcall: bool,
+}
+
+enum Tag {
+    Incomplete,
+    Control,
+    Text,
+}
+
+impl ReplyFilter {
+    pub fn new() -> Self {
+        Self {
+            pending: String::new(),
+            prefix_checked: false,
+            started: false,
+            is_endcall: false,
+        }
+    }
+
+    /// Add the next piece; returns the text that is now safe to show.
+    pub fn push(&mut self, piece: &str) -> String {
+        self.pending.push_str(piece);
+        let mut out = String::new();
+
+        while !self.pending.is_empty() {
+            if self.pending.starts_with('[') {
+                match self.take_tag() {
+                    Tag::Incomplete => break,
+                    Tag::Control => {}
+                    Tag::Text => {
+                        // Not a tag, so the bracket is ordinary text
+                        self.pending.remove(0);
+                        self.started = true;
+                        out.push('[');
+                    }
+                }
+                continue;
+            }
+
+            if !self.started {
+                // Leading whitespace and the `Bot:` prefix are dropped
+                let trimmed = self.pending.len() - self.pending.trim_start().len();
+                self.pending.drain(..trimmed);
+                if self.pending.is_empty() || self.pending.starts_with('[') {
+                    continue;
+                }
+
+                if !self.prefix_checked {
+                    if BOT_PREFIXES
+                        .iter()
+                        .any(|p| p.len() > self.pending.len() && p.starts_with(self.pending.as_str()))
+                    {
+                        break;
+                    }
+                    if let Some(prefix) = BOT_PREFIXES.iter().find(|p| self.pending.starts_with(*p)) {
+                        self.pending.drain(..prefix.len());
+                    }
+                    self.prefix_checked = true;
+                    continue;
+                }
+                self.started = true;
+            }
+
+            let end = self.pending.find('[').unwrap_or(self.pending.len());
+            out.extend(self.pending.drain(..end));
+        }
+
+        out
+    }
+
+    /// Release whatever was held back once the reply is complete. A control
+    /// tag cut off by the end of the reply, e.g. `[ENDCA`, is still dropped.
+    pub fn finish(&mut self) -> String {
+        let pending = std::mem::take(&mut self.pending);
+
+        if let Some(name) = pending.strip_prefix('[')
+            && let Some(tag) = CONTROL_TAGS
+                .iter()
+                .find(|t| t.len() >= name.len() && t[..name.len()].eq_ignore_ascii_case(name))
+        {
+            if tag.len() == name.len() && *tag == "endcall" {
+                self.is_endcall = true;
+            }
+            return String::new();
+        }
+        pending
+    }
+
+    pub fn is_endcall(&self) -> bool {
+        self.is_endcall
+    }
+
+    /// `pending` starts with `[`: consume a complete control tag, or decide
+    /// the bracket is plain text, or wait for more input.
+    fn take_tag(&mut self) -> Tag {
+        let name_end = self.pending[1..]
+            .find(|c: char| !(c.is_ascii_alphabetic() || c == '_'))
+            .map(|i| i + 1);
+
+        match name_end {
+            None if self.pending.len() > MAX_TAG_LEN => Tag::Text,
+            None => Tag::Incomplete,
+            Some(end) if self.pending[end..].starts_with(']') => {
+                let name = &self.pending[1..end];
+                if !CONTROL_TAGS.iter().any(|t| t.eq_ignore_ascii_case(name)) {
+                    return Tag::Text;
+                }
+                if name.eq_ignore_ascii_case("endcall") {
+                    self.is_endcall = true;
+                }
+                self.pending.drain(..=end);
+                Tag::Control
+            }
+            Some(_) => Tag::Text,
+        }
+    }
+}
+
+// ----- Tests ----- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Shown text and the end-call flag after feeding `pieces` in order
+    fn feed(pieces: &[&str]) -> (String, bool) {
+        let mut filter = ReplyFilter::new();
+        let mut out: String = pieces.iter().map(|piece| filter.push(piece)).collect();
+        out.push_str(&filter.finish());
+        (out, filter.is_endcall())
+    }
+
+    // Every way of cutting `reply` into two and three pieces
+    fn splits(reply: &str) -> Vec<Vec<&str>> {
+        let mut all = vec![vec![reply]];
+        for i in 0..=reply.len() {
+            all.push(vec![&reply[..i], &reply[i..]]);
+            for j in i..=reply.len() {
+                all.push(vec![&reply[..i], &reply[i..j], &reply[j..]]);
+            }
+        }
+        all
+    }
+
+    #[test]
+    fn drops_tags_split_at_every_boundary() {
+        let cases = [
+            ("Thanks, see you soon! [ENDCALL]", "Thanks, see you soon! "),
+            ("Goodbye [endcall] for now", "Goodbye  for now"),
+            ("[EndCall]Bye", "Bye"),
+        ];
+        for (reply, shown) in cases {
+            for pieces in splits(reply) {
+                assert_eq!(feed(&pieces), (shown.to_string(), true), "{:?}", pieces);
+            }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn strips_bot_prefix_split_across_pieces() {
+        for reply in ["Bot: Hello there", "BOT:Hello there", "  Bot: Hello there"] {
+            for pieces in splits(reply) {
+                let (shown, _) = feed(&pieces);
+                assert_eq!(shown.trim(), "Hello there", "{:?}", pieces);
+            }
+        }
+        // Only a leading prefix is dropped, and only a whole one
+        assert_eq!(feed(&["Bo", "ttle"]).0, "Bottle");
+        assert_eq!(feed(&["Hi, Bot: there"]).0, "Hi, Bot: there");
+        assert_eq!(feed(&["Bot: ", "Bot: twice"]).0, "Bot: twice");
+    }
+
+    #[test]
+    fn keeps_brackets_that_are_not_tags() {
+        let cases = [
+            "see [sic] you",
+            "[1] first option",
+            "a [ b ] c",
+            "[note: call back]",
+            "prices [EUR]",
+            "[averyveryverylongbracketedword]",
+        ];
+        for reply in cases {
+            for pieces in splits(reply) {
+                assert_eq!(feed(&pieces), (reply.to_string(), false), "{:?}", pieces);
+            }
+        }
+    }
+
+    #[test]
+    fn drops_unterminated_tag_at_end_of_stream() {
+        assert_eq!(feed(&["Bye ", "[ENDCA"]), ("Bye ".to_string(), false));
+        assert_eq!(feed(&["Bye [", "endcall"]), ("Bye ".to_string(), true));
+        assert_eq!(feed(&["Bye ["]), ("Bye ".to_string(), false));
+        // Plain text cut off at the end is still shown
+        assert_eq!(feed(&["Look [here"]), ("Look [here".to_string(), false));
+    }
+
+    #[test]
+    fn parse_reply_matches_streaming() {
+        let reply = BotReply::parse_reply("Bot: See you soon! [ENDCALL]");
+        assert_eq!(reply.reply_string, "See you soon!");
+        assert!(reply.is_endcall);
+    }
+}
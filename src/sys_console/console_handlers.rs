@@ -1,29 +1,41 @@
 use crate::{
     sys_bot::bot_openai::ask_openai,
-    sys_core::{HttpResponse, core_responses::response_ok},
+    sys_core::{
+        HttpRequest, HttpResponse, Router, StatusCode,
+        core_responses::{response_error, response_ok},
+    },
     sys_db::db_sessions::{
         SessionRow, get_all_sessions, get_session_by_id, init_database, insert_session,
     },
 };
 use rusqlite::Connection;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
+use tracing::info_span;
 use uuid::Uuid;
 use regex::Regex;
 
+// ----- Routes ----- //
+
+pub fn register_routes(router: &mut Router) {
+    router.post("/api/cmd", handle_api_command);
+}
+
 // ----- API Handlers ----- //
 
-pub fn handle_api_command(body: &str) -> HttpResponse {
-    let parsed: Option<String> = serde_json::from_str::<Value>(body).ok().and_then(|v| {
+pub async fn handle_api_command(request: Arc<HttpRequest>) -> HttpResponse {
+    let parsed: Option<String> = serde_json::from_str::<Value>(request.body_str()).ok().and_then(|v| {
         v.get("command")
             .and_then(|c| c.as_str())
             .map(|s| s.to_string())
     });
 
-    let cmd = parsed.unwrap_or_else(|| "<empty>".to_string());
+    let Some(cmd) = parsed else {
+        return response_error(StatusCode::BadRequest, "Expected JSON body { \"command\": ... }");
+    };
     let (cmd_name, args) = parse_command(&cmd);
 
-    let msg = execute_command(&cmd_name, &args);
+    let msg = execute_command(&cmd_name, &args).await;
 
     response_ok("application/json; charset=utf-8", msg.into_bytes())
 }
@@ -59,21 +71,31 @@ fn parse_command(input: &str) -> (String, Vec<String>) {
 }
 
 
-fn execute_command(cmd: &str, args: &[String]) -> String {
+async fn execute_command(cmd: &str, args: &[String]) -> String {
     match cmd {
         "help" => command_help(args),
-        "test" => command_test(args),
+        "test" => command_test(args).await,
 
         // Database session management commands
-        "db_session_list" => db_session_list(args),
-        "db_session_get" => db_session_get(args),
-        "db_session_add" => db_session_add(args),
-        "db_session_delete" => db_session_delete(args),
+        "db_session_list" => run_blocking(cmd, db_session_list, args).await,
+        "db_session_get" => run_blocking(cmd, db_session_get, args).await,
+        "db_session_add" => run_blocking(cmd, db_session_add, args).await,
+        "db_session_delete" => run_blocking(cmd, db_session_delete, args).await,
 
         _ => command_not_supported(cmd, args),
     }
 }
 
+/// Database commands make blocking SQLite calls, so they run on the
+/// blocking thread pool, in a `db` span named after the command.
+async fn run_blocking(name: &str, command: fn(&[String]) -> String, args: &[String]) -> String {
+    let args = args.to_vec();
+    let span = info_span!("db", op = name);
+    tokio::task::spawn_blocking(move || span.in_scope(|| command(&args)))
+        .await
+        .unwrap_or_else(|e| format!(r#"{{"message":"Command failed: {}"}}"#, e))
+}
+
 // ----- Command Implementations ----- //
 
 fn command_help(_args: &[String]) -> String {
@@ -86,20 +108,20 @@ fn command_not_supported(cmd: &str, _args: &[String]) -> String {
 
 // ----- Test Commands ----- //
 
-fn command_test(args: &[String]) -> String {
-    match args.get(0).map(String::as_str) {
-        Some("echo") => command_test_echo(&args[1..]),
+async fn command_test(args: &[String]) -> String {
+    match args.first().map(String::as_str) {
+        Some("echo") => command_test_echo(&args[1..]).await,
         _ => r#"{"message":"Test what? Available: echo"}"#.to_string(),
     }
 }
 
-fn command_test_echo(args: &[String]) -> String {
+async fn command_test_echo(args: &[String]) -> String {
     let input = args.join(" ");
     let mut result = format!(r#"{{"message":"Echo: {}"}}"#, input);
 
     if !input.trim().is_empty() {
         let messages = vec![("user", input.as_str())];
-        match ask_openai(messages) {
+        match ask_openai(messages).await {
             Ok(reply) => {
                 result = format!(r#"{{"message":"AI: {}"}}"#, reply.replace('"', "\\\""));
             }
@@ -139,7 +161,7 @@ fn db_session_list(_args: &[String]) -> String {
 }
 
 fn db_session_get(args: &[String]) -> String {
-    let id = match args.get(0) {
+    let id = match args.first() {
         Some(id) => id,
         None => return r#"{"message":"Missing session ID"}"#.to_string(),
     };
@@ -241,7 +263,7 @@ fn db_session_add(args: &[String]) -> String {
 }
 
 fn db_session_delete(args: &[String]) -> String {
-    let id = match args.get(0) {
+    let id = match args.first() {
         Some(id) => id,
         None => return r#"{"message":"Missing session ID"}"#.to_string(),
     };
@@ -1,3 +1,3 @@
 mod console_handlers;
 
-pub use console_handlers::handle_api_command;
\ No newline at end of file
+pub use console_handlers::register_routes;
\ No newline at end of file
@@ -1,22 +1,156 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::sync::OnceLock;
 
-/// Configuration file structure.
-/// Now uses `port` (from cfg/config.json)
-/// and loads `bot_apikey` from the environment variable BOT_API_KEY.
+use crate::sys_resource::DEFAULT_CACHE_BYTES;
 
 const BOT_API_KEY_ENV: &str = "CHARMLINE_BOT_KEY";
 
+/// Configuration file structure.
+/// Now uses `port` (from cfg/config.json)
+/// and loads `bot_apikey` from the environment variable BOT_API_KEY.
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
-    #[serde(rename = "port")]
+    /// Plain HTTP port on 127.0.0.1, used when `listeners` is empty.
+    #[serde(rename = "port", default = "default_port")]
     pub port: u16,
+    #[serde(rename = "listeners", default)]
+    pub listeners: Vec<ListenerConfig>,
+    #[serde(rename = "server", default)]
+    pub server: ServerConfig,
+    #[serde(rename = "static", default)]
+    pub static_files: StaticConfig,
+    #[serde(rename = "tls", default)]
+    pub tls: TlsConfig,
+    #[serde(rename = "cors", default)]
+    pub cors: CorsConfig,
+    #[serde(rename = "logging", default)]
+    pub logging: LoggingConfig,
+    #[serde(rename = "health", default)]
+    pub health: HealthConfig,
+    #[serde(rename = "openai", default)]
+    pub openai: OpenAIConfig,
+    #[serde(rename = "branding", default)]
+    pub branding: BrandingConfig,
     #[serde(skip)]
     pub bot_apikey: String,
 }
 
+/// Connection handling settings (`"server"` section, every field optional).
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Seconds an idle keep-alive connection is held open between requests.
+    pub keep_alive_timeout_secs: u64,
+    /// Requests served on one connection before it is closed.
+    pub keep_alive_max_requests: usize,
+    /// Async runtime threads, 0 for one per CPU core.
+    pub worker_threads: usize,
+    /// Open connections, including WebSockets and event streams. Further
+    /// ones wait in the accept queue.
+    pub max_connections: usize,
+    /// Accepted connections allowed to wait for a free slot. Once the queue
+    /// is full new connections are turned away with `503`.
+    pub accept_queue: usize,
+    /// Seconds a queued connection waits for a slot before it gets the 503.
+    pub accept_queue_timeout_secs: u64,
+    /// `Retry-After` seconds sent with the 503 when the server is full.
+    pub busy_retry_after_secs: u64,
+    /// Seconds shutdown waits for in-flight requests and summary jobs.
+    pub shutdown_grace_secs: u64,
+    /// Longest wait for more bytes once a request has started arriving.
+    pub read_timeout_secs: u64,
+    /// Longest wait for a client to accept response bytes.
+    pub write_timeout_secs: u64,
+    /// Total time allowed to receive one request, headers and body.
+    pub request_timeout_secs: u64,
+    /// Header block size; larger requests get `431`.
+    pub max_header_bytes: usize,
+    /// Body size; larger requests get `413`.
+    pub max_body_bytes: usize,
+    /// Request target length; longer requests get `414`.
+    pub max_uri_bytes: usize,
+    /// Concurrent connections from one IP address, 0 for no limit. Behind a
+    /// TCP reverse proxy every client shares the proxy's address.
+    pub max_connections_per_ip: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            keep_alive_timeout_secs: 5,
+            keep_alive_max_requests: 100,
+            worker_threads: 0,
+            max_connections: 1024,
+            accept_queue: 128,
+            accept_queue_timeout_secs: 10,
+            busy_retry_after_secs: 2,
+            shutdown_grace_secs: 30,
+            read_timeout_secs: 10,
+            write_timeout_secs: 10,
+            request_timeout_secs: 30,
+            max_header_bytes: 16 * 1024,
+            max_body_bytes: 10 * 1024 * 1024,
+            max_uri_bytes: 8 * 1024,
+            max_connections_per_ip: 64,
+        }
+    }
+}
+
+fn default_port() -> u16 {
+    8080
+}
+
+/// One socket to accept connections on (`"listeners"` entries):
+///
+/// - `{"address": "0.0.0.0:8080"}` or `{"address": "[::]:8080"}` for TCP,
+///   with `"tls": true` to serve HTTPS using the `"tls"` certificate.
+/// - `{"unix": "/run/charmline.sock", "mode": "660"}` for a Unix domain
+///   socket, e.g. behind nginx. `mode` is octal and applied after binding.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ListenerConfig {
+    Tcp {
+        address: String,
+        #[serde(default)]
+        tls: bool,
+    },
+    Unix {
+        unix: String,
+        #[serde(default = "default_socket_mode")]
+        mode: String,
+    },
+}
+
+fn default_socket_mode() -> String {
+    "660".to_string()
+}
+
+impl AppConfig {
+    /// The configured listeners, or `port` (plus `tls.port` when TLS is
+    /// enabled) on 127.0.0.1 if none are listed.
+    pub fn listeners(&self) -> Vec<ListenerConfig> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+
+        let mut listeners = vec![ListenerConfig::Tcp {
+            address: format!("127.0.0.1:{}", self.port),
+            tls: false,
+        }];
+        if self.tls.enabled {
+            listeners.push(ListenerConfig::Tcp {
+                address: format!("127.0.0.1:{}", self.tls.port),
+                tls: true,
+            });
+        }
+        listeners
+    }
+}
+
 static CONFIG: OnceLock<AppConfig> = OnceLock::new();
 
 /// Load configuration:
@@ -32,9 +166,452 @@ pub fn load_config(path: &str) {
         .unwrap_or_else(|_| panic!("Environment variable {} is not set", BOT_API_KEY_ENV));
     parsed.bot_apikey = env_key;
 
+    if let Err(e) = parsed.branding.validate() {
+        panic!("Invalid branding config: {}", e);
+    }
+
     CONFIG.set(parsed).expect("Config already initialized");
 }
 
 pub fn get_config() -> &'static AppConfig {
     CONFIG.get().expect("Config not initialized")
 }
+
+/// All defaults, for tests of code that reads the config.
+#[cfg(test)]
+pub fn init_test_config() {
+    CONFIG.get_or_init(|| serde_json::from_str("{}").expect("Empty config should parse"));
+}
+
+/// Like `get_config`, for callers that report a missing config instead of
+/// panicking.
+pub fn try_get_config() -> Option<&'static AppConfig> {
+    CONFIG.get()
+}
+
+/// Static file serving settings (`"static"` section).
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StaticConfig {
+    /// `Cache-Control` per URL path prefix. The longest matching prefix wins.
+    pub cache_control: Vec<CacheControlRule>,
+    /// `Cache-Control` when no rule matches. `no-cache` still lets browsers
+    /// keep the file but makes them revalidate it with the ETag.
+    pub default_cache_control: String,
+    /// Development: cached files are reloaded when they change on disk and
+    /// every response says `no-cache`. Production caches each file once.
+    pub dev_mode: bool,
+    /// Read the whole static tree into the cache at startup (production only).
+    pub preload: bool,
+    /// Memory budget of the file cache, compressed variants included. The
+    /// least recently used files are dropped to stay within it.
+    pub cache_max_bytes: usize,
+    /// Files larger than this are streamed from disk on every request
+    /// instead of being cached. Unset, only files over the budget are.
+    pub cache_max_file_bytes: Option<usize>,
+    /// Builds with the `embed-static` feature serve the files compiled into
+    /// the binary; files in this directory, relative to the executable,
+    /// replace them. Ignored by builds that read `static/` from disk.
+    pub override_dir: Option<String>,
+    /// Which symlinks under the static directory are followed.
+    pub symlinks: SymlinkPolicy,
+    /// Extra or replacement types by extension, e.g. `{"glb": "model/gltf-binary"}`.
+    /// Textual types get `charset=utf-8` unless they name a charset.
+    pub mime_types: HashMap<String, String>,
+    /// File served for a directory, e.g. `/pages/` → `pages/index.html`.
+    pub index_file: String,
+    /// Files tried in order for an extensionless path that matched nothing,
+    /// with `{path}` standing for it. The default serves `/settings` from
+    /// `pages/settings.html`; `[]` turns clean URLs off.
+    pub clean_urls: Vec<String>,
+    /// Single-page apps: extensionless page requests that match nothing else
+    /// get this file with `200`, leaving the route to the client.
+    pub spa_fallback: Option<String>,
+    /// Body for error statuses by code, e.g. `{"404": "pages/404.html"}`.
+    /// Plain text when unset or when the page cannot be loaded.
+    pub error_pages: HashMap<u16, String>,
+    /// Files with these extensions are filled in by the template engine
+    /// (see `core_template`) before being served; `[]` serves them as-is.
+    pub template_extensions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Never serve a file reached through a symlink.
+    Deny,
+    /// Follow symlinks whose target is inside the static directory.
+    WithinRoot,
+    /// Follow symlinks anywhere, e.g. to assets shared with another site.
+    Follow,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CacheControlRule {
+    pub prefix: String,
+    pub value: String,
+}
+
+impl Default for StaticConfig {
+    fn default() -> Self {
+        Self {
+            cache_control: Vec::new(),
+            default_cache_control: "no-cache".to_string(),
+            dev_mode: false,
+            preload: false,
+            cache_max_bytes: DEFAULT_CACHE_BYTES,
+            cache_max_file_bytes: None,
+            override_dir: None,
+            symlinks: SymlinkPolicy::WithinRoot,
+            mime_types: HashMap::new(),
+            index_file: "index.html".to_string(),
+            clean_urls: vec!["pages/{path}.html".to_string()],
+            spa_fallback: None,
+            error_pages: HashMap::new(),
+            template_extensions: vec!["html".to_string()],
+        }
+    }
+}
+
+impl StaticConfig {
+    pub fn cache_control_for(&self, path: &str) -> &str {
+        self.cache_control
+            .iter()
+            .filter(|rule| path.starts_with(&rule.prefix))
+            .max_by_key(|rule| rule.prefix.len())
+            .map(|rule| rule.value.as_str())
+            .unwrap_or(&self.default_cache_control)
+    }
+}
+
+/// Certificate and HTTPS settings (`"tls"` section). Paths are relative to
+/// the working directory, like the config file itself.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// Add an HTTPS listener on `port` when `listeners` is empty. Explicit
+    /// listeners opt in with `"tls": true` instead.
+    pub enabled: bool,
+    /// Port of the HTTPS listener; `port` keeps serving plain HTTP.
+    pub port: u16,
+    pub cert_path: String,
+    pub key_path: String,
+    /// Answer plain HTTP requests on TCP listeners with a redirect to the
+    /// first HTTPS listener's port.
+    pub redirect_http: bool,
+    /// How often handshakes check the certificate files for changes.
+    pub reload_check_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8443,
+            cert_path: "cfg/tls/cert.pem".to_string(),
+            key_path: "cfg/tls/key.pem".to_string(),
+            redirect_http: false,
+            reload_check_secs: 10,
+        }
+    }
+}
+
+/// Cross-origin access (`"cors"` section). Routes under `public_prefixes`
+/// use the `public` policy, every other `/api/` route the `admin` policy.
+/// A policy with no allowed origins keeps its routes same-origin only.
+///
+/// A prefix covers the path itself and the paths below it, so
+/// `/api/session/get` does not cover `/api/session/getartifact`. The default
+/// opens only the chat endpoints; stored transcripts stay admin-only.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    pub public_prefixes: Vec<String>,
+    pub public: CorsPolicy,
+    pub admin: CorsPolicy,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            public_prefixes: [
+                "/api/session/start",
+                "/api/session/get",
+                "/api/session/sendinput",
+                "/api/session/stream",
+                "/api/session/ws/",
+            ]
+            .map(String::from)
+            .to_vec(),
+            public: CorsPolicy::default(),
+            admin: CorsPolicy::default(),
+        }
+    }
+}
+
+impl CorsConfig {
+    /// The policy covering `path`, or `None` outside the API.
+    pub fn policy_for(&self, path: &str) -> Option<&CorsPolicy> {
+        if self.public_prefixes.iter().any(|p| covers_path(p, path)) {
+            Some(&self.public)
+        } else if path.starts_with("/api/") {
+            Some(&self.admin)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CorsPolicy {
+    /// Exact origins such as `https://example.com`, or `*` for any origin.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers a preflight may ask for, matched case-insensitively.
+    pub allowed_headers: Vec<String>,
+    /// Response headers scripts may read besides the CORS-safelisted ones.
+    pub expose_headers: Vec<String>,
+    /// Allow cookies and `Authorization`. The request's origin is echoed
+    /// back instead of `*`, as browsers require.
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight result.
+    pub max_age_secs: u64,
+}
+
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            allowed_headers: vec!["Content-Type".to_string()],
+            expose_headers: Vec::new(),
+            allow_credentials: false,
+            max_age_secs: 600,
+        }
+    }
+}
+
+/// Log output (`"logging"` section). `RUST_LOG` overrides `level` when set.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Filter directives, e.g. `info` or `info,charmline::sys_bot=debug`.
+    pub level: String,
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines with the span fields inline.
+    Text,
+    /// One JSON object per line, for log shippers.
+    Json,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+/// Readiness probe settings (`"health"` section).
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Also call the LLM API on `/readyz`. Off by default since every probe
+    /// then costs a request to the provider.
+    pub check_llm: bool,
+    pub llm_timeout_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            check_llm: false,
+            llm_timeout_secs: 5,
+        }
+    }
+}
+
+/// Calls to the OpenAI API (`"openai"` section). A stalled call would
+/// otherwise hold its chat request, and shutdown, until the peer gives up.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct OpenAIConfig {
+    pub connect_timeout_secs: u64,
+    /// Total time for a reply that is not streamed.
+    pub request_timeout_secs: u64,
+    /// Longest wait for the response headers of a streamed reply, and
+    /// between its chunks. A long reply that keeps arriving is not cut off.
+    pub stream_idle_timeout_secs: u64,
+}
+
+impl Default for OpenAIConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 10,
+            request_timeout_secs: 60,
+            stream_idle_timeout_secs: 30,
+        }
+    }
+}
+
+/// Per-deployment names, logo and look for templated pages (`"branding"`
+/// section), available to them as `brand.*`. Extra keys are passed through,
+/// so a page can use `{{ brand.support_email }}` without a code change.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BrandingConfig {
+    /// Shown in page titles and headers.
+    pub product_name: String,
+    /// The client the deployment is for, e.g. `WakoModular`.
+    pub company_name: String,
+    /// What the assistant is called in the chat preview.
+    pub bot_name: String,
+    /// Header logo; none when empty.
+    pub logo_url: String,
+    /// CSS colours: `#4da3ff`, `rgb(77 163 255)`, `hsl(...)` or a name.
+    pub accent_color: String,
+    pub accent_hover_color: String,
+    /// CSS font families, loaded by the stylesheet at `font_url`. Letters,
+    /// digits, spaces and hyphens only.
+    pub heading_font: String,
+    pub body_font: String,
+    pub font_url: String,
+    pub footer_text: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl BrandingConfig {
+    /// Colours and fonts are written into a `<style>` block, where HTML
+    /// escaping does not stop a value from ending the rule or the element,
+    /// so only plain values are accepted.
+    pub fn validate(&self) -> Result<(), String> {
+        for (field, value) in [("accent_color", &self.accent_color), ("accent_hover_color", &self.accent_hover_color)] {
+            if !is_css_color(value) {
+                return Err(format!("{} {:?} is not a CSS colour", field, value));
+            }
+        }
+        for (field, value) in [("heading_font", &self.heading_font), ("body_font", &self.body_font)] {
+            if !is_font_family(value) {
+                return Err(format!("{} {:?} is not a font family name", field, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for BrandingConfig {
+    fn default() -> Self {
+        Self {
+            product_name: "Charmline".to_string(),
+            company_name: String::new(),
+            bot_name: "Charmline".to_string(),
+            logo_url: String::new(),
+            accent_color: "#4da3ff".to_string(),
+            accent_hover_color: "#3390ff".to_string(),
+            heading_font: "Outfit".to_string(),
+            body_font: "Manrope".to_string(),
+            font_url: "https://fonts.googleapis.com/css2?family=Lora:ital,wght@0,400..700;1,400..700&family=Manrope:wght@200..800&family=Outfit:wght@100..900&display=swap".to_string(),
+            footer_text: "Charmline © 2025".to_string(),
+            extra: HashMap::new(),
+        }
+    }
+}
+
+// `prefix` is `path` or one of its parent paths
+fn covers_path(prefix: &str, path: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || prefix.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+fn is_css_color(value: &str) -> bool {
+    if let Some(hex) = value.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    if let Some((function, args)) = value.strip_suffix(')').and_then(|v| v.split_once('(')) {
+        return matches!(function, "rgb" | "rgba" | "hsl" | "hsla")
+            && args.chars().all(|c| c.is_ascii_alphanumeric() || " .,%/+-".contains(c));
+    }
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+fn is_font_family(value: &str) -> bool {
+    !value.trim().is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ' || c == '-')
+}
+
+// ----- Tests ----- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_cors_policy_covers_only_chat_routes() {
+        let cors = CorsConfig::default();
+        let public = |path| std::ptr::eq(cors.policy_for(path).unwrap(), &cors.public);
+
+        for path in ["/api/session/start", "/api/session/get", "/api/session/stream", "/api/session/ws/abc"] {
+            assert!(public(path), "{}", path);
+        }
+        for path in ["/api/session/getartifact", "/api/session/listartifacts", "/api/sessions/abc", "/api/server/cache"] {
+            assert!(!public(path), "{}", path);
+        }
+        assert!(cors.policy_for("/index.html").is_none());
+    }
+
+    #[test]
+    fn accepts_plain_css_values() {
+        let colors = [
+            "#4da3ff",
+            "#FFF",
+            "#4da3ff80",
+            "rgb(77 163 255 / 50%)",
+            "rgba(77, 163, 255, 0.5)",
+            "hsl(210deg 100% 65%)",
+            "rebeccapurple",
+        ];
+        for color in colors {
+            assert!(is_css_color(color), "{}", color);
+        }
+        for font in ["Outfit", "Open Sans", "Source Code Pro", "M PLUS 1p"] {
+            assert!(is_font_family(font), "{}", font);
+        }
+        assert_eq!(BrandingConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_values_that_escape_the_style_block() {
+        let colors = [
+            "",
+            "#4da3f",
+            "#ggg",
+            "red; } body { display: none",
+            "red</style><script>alert(1)</script>",
+            "rgb(1,2,3); x: y",
+            "url(https://example.com)",
+            "expression(alert(1))",
+            "rgb(1 2 3) }",
+        ];
+        for color in colors {
+            assert!(!is_css_color(color), "{}", color);
+        }
+        for font in ["", "  ", "Outfit\"", "Outfit\"; } body { x: y", "Outfit</style>", "Outfit\\22"] {
+            assert!(!is_font_family(font), "{}", font);
+        }
+
+        let branding = BrandingConfig {
+            accent_color: "red;}".to_string(),
+            ..BrandingConfig::default()
+        };
+        assert!(branding.validate().is_err());
+    }
+}
@@ -0,0 +1,163 @@
+// ----- Imports ----- //
+
+use tracing::warn;
+
+use crate::sys_core::{
+    HttpRequest, HttpResponse, StatusCode,
+    core_config::CorsPolicy,
+    core_responses::{response_not_found, response_status},
+    core_router::get_router,
+    get_config,
+};
+
+// ----- Preflight ----- //
+
+/// Answer an `OPTIONS` request. A CORS preflight (`Origin` plus
+/// `Access-Control-Request-Method`) is checked against the path's policy;
+/// a plain `OPTIONS` just lists the methods the path supports.
+pub fn handle_options(request: &HttpRequest) -> HttpResponse {
+    let mut methods = get_router().methods_for(&request.path);
+    if methods.is_empty() {
+        if request.path.starts_with("/api/") {
+            return response_not_found("Unknown API route");
+        }
+        methods.push("GET"); // Static files
+    }
+
+    let (Some(origin), Some(requested_method)) = (
+        request.header("Origin"),
+        request.header("Access-Control-Request-Method"),
+    ) else {
+        methods.push("OPTIONS");
+        return HttpResponse::new(StatusCode::NoContent).with_header("Allow", methods.join(", "));
+    };
+
+    let requested_headers: Vec<&str> = request
+        .header("Access-Control-Request-Headers")
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|h| !h.is_empty())
+        .collect();
+
+    let policy = get_config().cors.policy_for(&request.path);
+    let allow_origin = policy.and_then(|p| allowed_origin(p, origin));
+
+    let (Some(policy), Some(allow_origin)) = (policy, allow_origin) else {
+        return reject_preflight(request, origin, "origin not allowed");
+    };
+    if !methods.contains(&requested_method) || !policy.allowed_methods.iter().any(|m| m == requested_method) {
+        return reject_preflight(request, origin, "method not allowed");
+    }
+    if let Some(header) = requested_headers
+        .iter()
+        .find(|h| !policy.allowed_headers.iter().any(|a| a.eq_ignore_ascii_case(h)))
+    {
+        return reject_preflight(request, origin, &format!("header '{}' not allowed", header));
+    }
+
+    let allow_methods: Vec<&str> = methods
+        .into_iter()
+        .filter(|m| policy.allowed_methods.iter().any(|a| a == m))
+        .collect();
+
+    let mut response = HttpResponse::new(StatusCode::NoContent)
+        .with_header("Access-Control-Allow-Origin", allow_origin)
+        .with_header("Access-Control-Allow-Methods", allow_methods.join(", "))
+        .with_header("Access-Control-Max-Age", policy.max_age_secs.to_string());
+    if !requested_headers.is_empty() {
+        response.set_header("Access-Control-Allow-Headers", requested_headers.join(", "));
+    }
+    if policy.allow_credentials {
+        response.set_header("Access-Control-Allow-Credentials", "true");
+    }
+    add_preflight_vary(&mut response);
+    response
+}
+
+/// Browsers treat a preflight without `Access-Control-Allow-Origin` as
+/// failed; the 403 and the log line are for whoever is debugging it.
+fn reject_preflight(request: &HttpRequest, origin: &str, reason: &str) -> HttpResponse {
+    warn!(origin, "Rejected preflight for {}: {}", request.path, reason);
+    let mut response = response_status(StatusCode::Forbidden, "CORS preflight rejected");
+    add_preflight_vary(&mut response);
+    response
+}
+
+fn add_preflight_vary(response: &mut HttpResponse) {
+    response.add_vary("Origin");
+    response.add_vary("Access-Control-Request-Method");
+    response.add_vary("Access-Control-Request-Headers");
+}
+
+// ----- Actual Requests ----- //
+
+/// Response pipeline step: let an allowed origin read the response,
+/// including error responses so scripts can show the message.
+pub fn apply_cors(request: &HttpRequest, response: &mut HttpResponse) {
+    let Some(policy) = get_config().cors.policy_for(&request.path) else {
+        return;
+    };
+    if policy.allowed_origins.is_empty() {
+        return;
+    }
+
+    // The answer depends on the origin, so shared caches must key on it
+    response.add_vary("Origin");
+
+    let Some(allow_origin) = request.header("Origin").and_then(|o| allowed_origin(policy, o)) else {
+        return;
+    };
+    response.set_header("Access-Control-Allow-Origin", allow_origin);
+    if policy.allow_credentials {
+        response.set_header("Access-Control-Allow-Credentials", "true");
+    }
+    if !policy.expose_headers.is_empty() {
+        response.set_header("Access-Control-Expose-Headers", policy.expose_headers.join(", "));
+    }
+}
+
+// ----- WebSocket Origins ----- //
+
+/// Browsers do not apply CORS to WebSockets, so the handshake checks the
+/// origin itself: same-origin pages and origins the path's policy allows
+/// may connect. Clients that send no `Origin` (non-browsers) are let through.
+pub fn is_origin_allowed(request: &HttpRequest) -> bool {
+    let Some(origin) = request.header("Origin") else {
+        return true;
+    };
+
+    let origin_host = origin.split_once("://").map_or(origin, |(_, host)| host);
+    if request
+        .header("Host")
+        .is_some_and(|host| host.eq_ignore_ascii_case(origin_host))
+    {
+        return true;
+    }
+
+    get_config()
+        .cors
+        .policy_for(&request.path)
+        .and_then(|policy| allowed_origin(policy, origin))
+        .is_some()
+}
+
+// ----- Helpers ----- //
+
+/// `Access-Control-Allow-Origin` value for `origin`, if the policy allows it.
+/// `*` is only sent back without credentials; otherwise the origin is echoed.
+fn allowed_origin(policy: &CorsPolicy, origin: &str) -> Option<String> {
+    if policy.allowed_origins.iter().any(|o| o == "*") {
+        return Some(if policy.allow_credentials {
+            origin.to_string()
+        } else {
+            "*".to_string()
+        });
+    }
+
+    policy
+        .allowed_origins
+        .iter()
+        .any(|o| o.eq_ignore_ascii_case(origin))
+        .then(|| origin.to_string())
+}
@@ -0,0 +1,144 @@
+// ----- Imports ----- //
+
+use std::io::Write;
+
+use flate2::{Compression, write::GzEncoder};
+
+use crate::sys_core::{HttpRequest, HttpResponse};
+
+// ----- Constants ----- //
+
+const MIN_COMPRESS_BYTES: usize = 512; // Smaller bodies are not worth the CPU
+const BROTLI_QUALITY: u32 = 5; // Fast enough for on-the-fly JSON
+const BROTLI_WINDOW: u32 = 22;
+
+// ----- Structs ----- //
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+// ----- Implementations ----- //
+
+impl Encoding {
+    pub fn header_value(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+// ----- Negotiation ----- //
+
+/// Pick the best encoding the client accepts from `Accept-Encoding`,
+/// preferring brotli over gzip when both have the same weight.
+pub fn negotiate_encoding(request: &HttpRequest) -> Option<Encoding> {
+    let header = request.header("Accept-Encoding")?;
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for candidate in [Encoding::Brotli, Encoding::Gzip] {
+        let q = quality_for(header, candidate.header_value());
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((candidate, q));
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+/// q-value of `coding` in an `Accept-Encoding` header, falling back to `*`.
+fn quality_for(header: &str, coding: &str) -> f32 {
+    let mut wildcard = 0.0;
+
+    for item in header.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or("").trim();
+        let q = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if name.eq_ignore_ascii_case(coding) {
+            return q;
+        }
+        if name == "*" {
+            wildcard = q;
+        }
+    }
+
+    wildcard
+}
+
+// ----- Compression ----- //
+
+pub fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime,
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "image/x-icon"
+                | "image/bmp"
+                | "font/ttf"
+                | "font/otf"
+                | "application/vnd.ms-fontobject"
+        )
+}
+
+pub fn compress(bytes: &[u8], encoding: Encoding) -> Vec<u8> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder
+                .write_all(bytes)
+                .and_then(|_| encoder.finish())
+                .expect("Writing to an in-memory gzip encoder cannot fail")
+        }
+        Encoding::Brotli => {
+            let mut out = Vec::new();
+            {
+                let mut writer =
+                    brotli::CompressorWriter::new(&mut out, 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+                writer
+                    .write_all(bytes)
+                    .expect("Writing to an in-memory brotli encoder cannot fail");
+            }
+            out
+        }
+    }
+}
+
+/// Response pipeline step for routed responses: compress compressible bodies
+/// the handler left unencoded, on a blocking thread. Static files are not
+/// passed here, as the loader cache compresses each once. A body with an
+/// `ETag` is left alone, since the tag names that exact representation.
+pub async fn encode_response(request: &HttpRequest, response: &mut HttpResponse) {
+    if !is_compressible(&response.content_type) {
+        return;
+    }
+    response.add_vary("Accept-Encoding");
+
+    if response.header("Content-Encoding").is_some()
+        || response.header("ETag").is_some()
+        || response.body.len() < MIN_COMPRESS_BYTES
+        || !response.status.allows_body()
+    {
+        return;
+    }
+
+    if let Some(encoding) = negotiate_encoding(request) {
+        let body = std::mem::take(&mut response.body);
+        response.body = tokio::task::spawn_blocking(move || compress(&body, encoding))
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+        response.set_header("Content-Encoding", encoding.header_value());
+    }
+}
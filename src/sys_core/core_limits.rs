@@ -0,0 +1,136 @@
+// ----- Imports ----- //
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use serde_json::{Map, json};
+
+use crate::sys_core::{HttpRequest, HttpResponse, core_responses::response_json, get_config};
+
+// ----- Global Limit State ----- //
+
+static CONNECTIONS_PER_IP: LazyLock<Mutex<HashMap<IpAddr, usize>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static REJECTIONS: [AtomicU64; Rejection::ALL.len()] = [const { AtomicU64::new(0) }; Rejection::ALL.len()];
+
+// ----- Structs ----- //
+
+/// Why a connection or request was turned away.
+#[derive(Clone, Copy, Debug)]
+pub enum Rejection {
+    BadRequest,
+    Timeout,
+    HeadersTooLarge,
+    BodyTooLarge,
+    UriTooLong,
+    UnsupportedEncoding,
+    PerIpLimit,
+    Busy,
+}
+
+/// One open connection counted against its client's address. The count
+/// drops when the connection (and thus the slot) is dropped.
+pub struct IpSlot(IpAddr);
+
+// ----- Implementations ----- //
+
+impl Rejection {
+    const ALL: [Rejection; 8] = [
+        Rejection::BadRequest,
+        Rejection::Timeout,
+        Rejection::HeadersTooLarge,
+        Rejection::BodyTooLarge,
+        Rejection::UriTooLong,
+        Rejection::UnsupportedEncoding,
+        Rejection::PerIpLimit,
+        Rejection::Busy,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rejection::BadRequest => "bad_request",
+            Rejection::Timeout => "timeout",
+            Rejection::HeadersTooLarge => "headers_too_large",
+            Rejection::BodyTooLarge => "body_too_large",
+            Rejection::UriTooLong => "uri_too_long",
+            Rejection::UnsupportedEncoding => "unsupported_encoding",
+            Rejection::PerIpLimit => "per_ip_limit",
+            Rejection::Busy => "busy",
+        }
+    }
+}
+
+impl Drop for IpSlot {
+    fn drop(&mut self) {
+        let mut connections = CONNECTIONS_PER_IP.lock().unwrap();
+        if let Some(count) = connections.get_mut(&self.0) {
+            *count -= 1;
+            if *count == 0 {
+                connections.remove(&self.0);
+            }
+        }
+    }
+}
+
+// ----- Limits ----- //
+
+/// Claim a connection slot for `ip`, or `None` when it already has
+/// `max_connections_per_ip` open.
+pub fn acquire_ip_slot(ip: IpAddr) -> Option<IpSlot> {
+    let max = get_config().server.max_connections_per_ip;
+    let mut connections = CONNECTIONS_PER_IP.lock().unwrap();
+    let count = connections.entry(ip).or_insert(0);
+    if max > 0 && *count >= max {
+        return None;
+    }
+    *count += 1;
+    Some(IpSlot(ip))
+}
+
+pub fn count_rejection(rejection: Rejection) {
+    REJECTIONS[rejection as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Rejection totals by reason, for monitoring.
+pub fn rejection_counts() -> Vec<(&'static str, u64)> {
+    Rejection::ALL
+        .iter()
+        .map(|r| (r.name(), REJECTIONS[*r as usize].load(Ordering::Relaxed)))
+        .collect()
+}
+
+// ----- Route Handlers ----- //
+
+/// GET /api/server/limits
+/// Returns the configured limits and how often each one rejected a client.
+pub async fn handle_server_limits(_request: Arc<HttpRequest>) -> HttpResponse {
+    let config = &get_config().server;
+
+    let rejected: Map<String, serde_json::Value> = rejection_counts()
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), json!(count)))
+        .collect();
+
+    let json = json!({
+        "limits": {
+            "read_timeout_secs": config.read_timeout_secs,
+            "write_timeout_secs": config.write_timeout_secs,
+            "request_timeout_secs": config.request_timeout_secs,
+            "max_header_bytes": config.max_header_bytes,
+            "max_body_bytes": config.max_body_bytes,
+            "max_uri_bytes": config.max_uri_bytes,
+            "max_connections_per_ip": config.max_connections_per_ip,
+        },
+        "client_addresses": CONNECTIONS_PER_IP.lock().unwrap().len(),
+        "rejected": rejected,
+    });
+
+    response_json(&json)
+}
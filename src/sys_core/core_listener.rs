@@ -0,0 +1,156 @@
+// ----- Imports ----- //
+
+use std::{io, net::IpAddr, sync::Arc};
+
+use tokio::net::TcpListener;
+
+#[cfg(unix)]
+use std::{
+    fs,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+};
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+use crate::sys_core::{core_config::ListenerConfig, core_stream::ClientStream, core_tls::TlsAcceptor};
+
+// ----- Structs ----- //
+
+/// A bound socket from one `"listeners"` entry.
+pub enum Listener {
+    Tcp {
+        listener: TcpListener,
+        address: String,
+        tls: Option<Arc<TlsAcceptor>>,
+    },
+    #[cfg(unix)]
+    Unix(UnixSocket),
+}
+
+/// Unix listener that removes its socket file when dropped, so a clean
+/// shutdown does not leave a stale path behind for the next start.
+#[cfg(unix)]
+pub struct UnixSocket {
+    listener: UnixListener,
+    path: String,
+}
+
+/// A connection fresh off a listener. HTTPS connections still owe their TLS
+/// handshake, which `establish` runs on the connection's own task.
+pub struct Accepted {
+    stream: ClientStream,
+    tls: Option<Arc<TlsAcceptor>>,
+    pub peer: Option<IpAddr>,
+}
+
+// ----- Implementations ----- //
+
+impl Listener {
+    /// Bind the socket described by `config`. `tls` is the shared acceptor
+    /// for entries with `"tls": true`.
+    pub async fn bind(config: &ListenerConfig, tls: Option<&Arc<TlsAcceptor>>) -> Result<Self, String> {
+        let listener = match config {
+            ListenerConfig::Tcp { address, tls: wants_tls } => {
+                let listener = TcpListener::bind(address)
+                    .await
+                    .map_err(|e| format!("Failed to bind {}: {}", address, e))?;
+                let tls = match (wants_tls, tls) {
+                    (false, _) => None,
+                    (true, Some(acceptor)) => Some(Arc::clone(acceptor)),
+                    (true, None) => return Err(format!("No TLS acceptor for {}", address)),
+                };
+                Listener::Tcp {
+                    listener,
+                    address: address.clone(),
+                    tls,
+                }
+            }
+            ListenerConfig::Unix { unix, mode } => bind_unix(unix, mode)?,
+        };
+        Ok(listener)
+    }
+
+    /// Where the listener is reachable, for startup logs.
+    pub fn url(&self) -> String {
+        match self {
+            Listener::Tcp { address, tls: None, .. } => format!("http://{}/", address),
+            Listener::Tcp { address, tls: Some(_), .. } => format!("https://{}/", address),
+            #[cfg(unix)]
+            Listener::Unix(socket) => format!("unix:{}", socket.path),
+        }
+    }
+
+    /// Wait for the next connection, with the peer's IP for TCP connections.
+    pub async fn accept(&self) -> io::Result<Accepted> {
+        match self {
+            Listener::Tcp { listener, tls, .. } => {
+                let (stream, peer) = listener.accept().await?;
+                Ok(Accepted {
+                    stream: ClientStream::Plain(stream),
+                    tls: tls.clone(),
+                    peer: Some(peer.ip()),
+                })
+            }
+            #[cfg(unix)]
+            Listener::Unix(socket) => {
+                let (stream, _) = socket.listener.accept().await?;
+                Ok(Accepted {
+                    stream: ClientStream::Unix(stream),
+                    tls: None,
+                    peer: None,
+                })
+            }
+        }
+    }
+}
+
+impl Accepted {
+    /// The stream to serve requests on, after the TLS handshake for HTTPS
+    /// listeners. The caller bounds how long this may take.
+    pub async fn establish(self) -> io::Result<ClientStream> {
+        match (self.stream, self.tls) {
+            (ClientStream::Plain(stream), Some(acceptor)) => acceptor.accept(stream).await,
+            (stream, _) => Ok(stream),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// ----- Helpers ----- //
+
+/// Bind a Unix socket, replacing a socket file left by a previous run, and
+/// apply the configured permissions.
+#[cfg(unix)]
+fn bind_unix(path: &str, mode: &str) -> Result<Listener, String> {
+    let mode = u32::from_str_radix(mode, 8)
+        .map_err(|_| format!("Invalid socket mode '{}' for {} (expected octal, e.g. 660)", mode, path))?;
+
+    // Only ever remove sockets, never a regular file someone pointed us at
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(format!("{} exists and is not a socket", path));
+        }
+        fs::remove_file(path).map_err(|e| format!("Failed to remove stale socket {}: {}", path, e))?;
+    }
+
+    let listener = UnixListener::bind(path).map_err(|e| format!("Failed to bind {}: {}", path, e))?;
+    let socket = UnixSocket {
+        listener,
+        path: path.to_string(),
+    };
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .map_err(|e| format!("Failed to set permissions on {}: {}", path, e))?;
+
+    Ok(Listener::Unix(socket))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(path: &str, _mode: &str) -> Result<Listener, String> {
+    Err(format!("Unix socket {} is not supported on this platform", path))
+}
@@ -0,0 +1,202 @@
+// ----- Imports ----- //
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::sys_core::{HttpRequest, HttpResponse, core_responses::response_ok};
+
+// ----- Constants ----- //
+
+/// Upper bounds of the latency buckets, in seconds. Wide enough for LLM calls.
+const LATENCY_BUCKETS: [f64; 14] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+// ----- Global Registry ----- //
+
+/// Every series recorded so far, by metric name. A metric shows up on its
+/// first observation.
+static REGISTRY: Mutex<BTreeMap<&'static str, Family>> = Mutex::new(BTreeMap::new());
+
+/// Values read when `/metrics` is scraped rather than tracked as they change.
+static GAUGES: Mutex<Vec<Gauge>> = Mutex::new(Vec::new());
+
+// ----- Structs ----- //
+
+/// A monotonically increasing count, e.g. `charmline_http_requests_total`.
+/// Declared as a static next to the code that increments it.
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+}
+
+/// Latency distribution in seconds over `LATENCY_BUCKETS`.
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+}
+
+struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    read: fn() -> f64,
+}
+
+struct Family {
+    help: &'static str,
+    kind: &'static str,
+    /// Keyed by the rendered label set, e.g. `route="/metrics",status="200"`.
+    series: BTreeMap<String, Series>,
+}
+
+enum Series {
+    Counter(u64),
+    Histogram {
+        buckets: [u64; LATENCY_BUCKETS.len()],
+        sum: f64,
+        count: u64,
+    },
+}
+
+// ----- Implementations ----- //
+
+impl Counter {
+    pub const fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self { name, help, labels }
+    }
+
+    /// `values` line up with the label names the counter was declared with.
+    pub fn inc(&self, values: &[&str]) {
+        self.add(values, 1);
+    }
+
+    pub fn add(&self, values: &[&str], amount: u64) {
+        record(self.name, self.help, "counter", self.labels, values, |series| {
+            if let Series::Counter(total) = series {
+                *total += amount;
+            }
+        });
+    }
+}
+
+impl Histogram {
+    pub const fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self { name, help, labels }
+    }
+
+    pub fn observe(&self, values: &[&str], elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        record(self.name, self.help, "histogram", self.labels, values, |series| {
+            if let Series::Histogram { buckets, sum, count } = series {
+                for (bucket, bound) in buckets.iter_mut().zip(LATENCY_BUCKETS) {
+                    if seconds <= bound {
+                        *bucket += 1;
+                    }
+                }
+                *sum += seconds;
+                *count += 1;
+            }
+        });
+    }
+}
+
+/// Expose a value that already lives elsewhere, e.g. the session count.
+pub fn register_gauge(name: &'static str, help: &'static str, read: fn() -> f64) {
+    GAUGES.lock().unwrap().push(Gauge { name, help, read });
+}
+
+// ----- Route Handlers ----- //
+
+/// GET /metrics
+/// Every metric in the Prometheus text exposition format.
+pub async fn handle_metrics(_request: Arc<HttpRequest>) -> HttpResponse {
+    response_ok("text/plain; version=0.0.4; charset=utf-8", render().into_bytes())
+}
+
+// ----- Helpers ----- //
+
+fn record(
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    labels: &[&str],
+    values: &[&str],
+    update: impl FnOnce(&mut Series),
+) {
+    let key = labels
+        .iter()
+        .zip(values)
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape_label(value)))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut registry = REGISTRY.lock().unwrap();
+    let family = registry.entry(name).or_insert_with(|| Family {
+        help,
+        kind,
+        series: BTreeMap::new(),
+    });
+    let series = family.series.entry(key).or_insert_with(|| match kind {
+        "histogram" => Series::Histogram {
+            buckets: [0; LATENCY_BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        },
+        _ => Series::Counter(0),
+    });
+    update(series);
+}
+
+fn render() -> String {
+    let mut out = String::new();
+
+    // Read outside the registry lock, since a gauge may take locks of its own
+    let gauges: Vec<(&str, &str, f64)> = GAUGES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|g| (g.name, g.help, (g.read)()))
+        .collect();
+    for (name, help, value) in gauges {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}", name, help, name, name, value);
+    }
+
+    for (name, family) in REGISTRY.lock().unwrap().iter() {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, family.help, name, family.kind);
+        for (labels, series) in &family.series {
+            match series {
+                Series::Counter(total) => {
+                    let _ = writeln!(out, "{}{} {}", name, braced(labels), total);
+                }
+                Series::Histogram { buckets, sum, count } => {
+                    let separator = if labels.is_empty() { "" } else { "," };
+                    for (bucket, bound) in buckets.iter().zip(LATENCY_BUCKETS) {
+                        let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, bucket);
+                    }
+                    let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, count);
+                    let _ = writeln!(out, "{}_sum{} {}", name, braced(labels), sum);
+                    let _ = writeln!(out, "{}_count{} {}", name, braced(labels), count);
+                }
+            }
+        }
+    }
+    out
+}
+
+fn braced(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels)
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,154 @@
+// ----- Imports ----- //
+
+use std::path::Path;
+
+use crate::sys_core::get_config;
+
+// ----- Constants ----- //
+
+/// Leading bytes examined when a file's extension is unknown.
+pub const SNIFF_BYTES: usize = 512;
+
+const FALLBACK_TYPE: &str = "application/octet-stream";
+
+// ----- Lookup ----- //
+
+/// `Content-Type` for a static file from its extension: `static.mime_types`
+/// first, then the built-in table. `None` when neither knows it, in which
+/// case the content is sniffed.
+pub fn content_type_for(path: &str) -> Option<String> {
+    let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+
+    let configured = get_config()
+        .static_files
+        .mime_types
+        .iter()
+        .find(|(ext, _)| ext.trim_start_matches('.').eq_ignore_ascii_case(&extension))
+        .map(|(_, mime)| mime.as_str());
+
+    configured
+        .or_else(|| builtin_type(&extension))
+        .map(with_charset)
+}
+
+/// `Content-Type` from the first bytes of a file with an unknown extension.
+/// Never guesses HTML, so an upload cannot turn into a page that runs script.
+pub fn sniff_content_type(bytes: &[u8]) -> String {
+    let head = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    let mime = magic_type(head).unwrap_or_else(|| if looks_like_text(head) { "text/plain" } else { FALLBACK_TYPE });
+    with_charset(mime)
+}
+
+// ----- Helpers ----- //
+
+fn builtin_type(extension: &str) -> Option<&'static str> {
+    Some(match extension {
+        // Documents and code
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "application/javascript",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "xml" => "application/xml",
+        "txt" | "log" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "csv" => "text/csv",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        // Images
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        // Fonts
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "eot" => "application/vnd.ms-fontobject",
+        // Audio and video
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" | "oga" => "audio/ogg",
+        "opus" => "audio/opus",
+        "flac" => "audio/flac",
+        "m4a" => "audio/mp4",
+        "weba" => "audio/webm",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "ogv" => "video/ogg",
+        // Archives
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        _ => return None,
+    })
+}
+
+// Textual types are always sent as UTF-8, the encoding of everything in
+// static/, unless the configured type already names a charset
+fn with_charset(mime: &str) -> String {
+    let essence = mime.split(';').next().unwrap_or("").trim();
+    let textual = essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(essence, "application/javascript" | "application/json" | "application/xml");
+
+    if textual && !mime.to_ascii_lowercase().contains("charset=") {
+        format!("{}; charset=utf-8", mime)
+    } else {
+        mime.to_string()
+    }
+}
+
+// Signatures from the WHATWG MIME Sniffing standard, plus fonts and wasm
+fn magic_type(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"\x00\x00\x01\x00", "image/x-icon"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b\x08", "application/gzip"),
+        (b"\x00asm", "application/wasm"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+        (b"\x00\x01\x00\x00", "font/ttf"),
+        (b"OTTO", "font/otf"),
+        (b"ID3", "audio/mpeg"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(mime);
+    }
+
+    // Container formats name their content type a few bytes in
+    match (head.get(..4), head.get(4..8), head.get(8..12)) {
+        (Some(b"RIFF"), _, Some(b"WEBP")) => Some("image/webp"),
+        (Some(b"RIFF"), _, Some(b"WAVE")) => Some("audio/wav"),
+        (_, Some(b"ftyp"), Some(b"avif")) => Some("image/avif"),
+        (_, Some(b"ftyp"), _) => Some("video/mp4"),
+        // MP3 without an ID3 tag starts on an MPEG frame sync
+        _ if head.len() >= 2 && head[0] == 0xff && head[1] & 0xe0 == 0xe0 => Some("audio/mpeg"),
+        _ => None,
+    }
+}
+
+// UTF-8 without the control bytes binary formats are full of. A multibyte
+// character cut off at the end of the sample still counts.
+fn looks_like_text(head: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    valid && !head.iter().any(|&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+}
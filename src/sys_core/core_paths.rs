@@ -0,0 +1,189 @@
+// ----- Constants ----- //
+
+/// Hidden directory that is still served, for ACME challenges and other
+/// well-known URIs (RFC 8615).
+const WELL_KNOWN: &str = ".well-known";
+
+// ----- Structs ----- //
+
+/// Why a request path was refused before touching the filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathRejection {
+    /// Bad `%` escape, invalid UTF-8, control characters, backslashes or
+    /// names Windows would alias to another file.
+    Malformed,
+    /// `..` segments that climb above the static root.
+    Traversal,
+    /// Dotfiles (`.git`, `.env`) and editor backups (`index.html~`).
+    Hidden,
+}
+
+// ----- Resolution ----- //
+
+/// Turn a URL path into a file name relative to the static root, with `/`
+/// separators: percent-decoded once, `.` and empty segments dropped, `..`
+/// applied. `""` is the root itself.
+pub fn resolve_static_path(url_path: &str) -> Result<String, PathRejection> {
+    let decoded = percent_decode_strict(url_path).ok_or(PathRejection::Malformed)?;
+    normalize_static_path(&decoded)
+}
+
+/// Like `resolve_static_path` for a name that is already decoded, such as a
+/// file name from the config or a template: `%` is taken literally.
+pub fn normalize_static_path(name: &str) -> Result<String, PathRejection> {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in name.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop().ok_or(PathRejection::Traversal)?;
+            }
+            _ => {
+                check_segment(segment)?;
+                segments.push(segment);
+            }
+        }
+    }
+    Ok(segments.join("/"))
+}
+
+/// Dotfiles and `~` backups are never served, nor preloaded.
+pub fn is_hidden_name(name: &str) -> bool {
+    (name.starts_with('.') && name != WELL_KNOWN) || name.ends_with('~')
+}
+
+// ----- Helpers ----- //
+
+fn check_segment(segment: &str) -> Result<(), PathRejection> {
+    let malformed = segment.chars().any(|c| c.is_control() || c == '\\' || c == ':')
+        // Windows drops trailing dots and spaces, so `index.html.` is `index.html`
+        || segment.ends_with('.')
+        || segment.ends_with(' ');
+
+    if malformed {
+        Err(PathRejection::Malformed)
+    } else if is_hidden_name(segment) {
+        Err(PathRejection::Hidden)
+    } else {
+        Ok(())
+    }
+}
+
+// Unlike `percent_decode`, a bad escape or invalid UTF-8 is an error rather
+// than passed through, so no two spellings of a path reach the filesystem
+fn percent_decode_strict(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            let hex = std::str::from_utf8(hex).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(out).ok()
+}
+
+// ----- Tests ----- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_plain_paths() {
+        assert_eq!(resolve_static_path("/"), Ok(String::new()));
+        assert_eq!(resolve_static_path("/index.html"), Ok("index.html".to_string()));
+        assert_eq!(resolve_static_path("/style/style.css"), Ok("style/style.css".to_string()));
+        assert_eq!(resolve_static_path("/pages/my%20page.html"), Ok("pages/my page.html".to_string()));
+        assert_eq!(resolve_static_path("/.well-known/acme"), Ok(".well-known/acme".to_string()));
+    }
+
+    #[test]
+    fn normalises_dot_and_empty_segments() {
+        assert_eq!(resolve_static_path("//style//./style.css"), Ok("style/style.css".to_string()));
+        assert_eq!(resolve_static_path("/style/../index.html"), Ok("index.html".to_string()));
+        assert_eq!(resolve_static_path("/a/b/../../index.html"), Ok("index.html".to_string()));
+        assert_eq!(resolve_static_path("/%2e/index.html"), Ok("index.html".to_string()));
+    }
+
+    #[test]
+    fn rejects_traversal_payloads() {
+        let payloads = [
+            "/..",
+            "/../",
+            "/../cfg/config.json",
+            "/../../../../etc/passwd",
+            "/style/../../cfg/config.json",
+            "/%2e%2e/cfg/config.json",
+            "/%2E%2E/cfg/config.json",
+            "/.%2e/cfg/config.json",
+            "/%2e./cfg/config.json",
+            "/%2e%2e%2fcfg%2fconfig.json",
+            "/style%2f..%2f..%2fcfg/config.json",
+            "/./../cfg/config.json",
+            "//../cfg/config.json",
+        ];
+        for payload in payloads {
+            assert_eq!(resolve_static_path(payload), Err(PathRejection::Traversal), "{}", payload);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_payloads() {
+        let payloads = [
+            "/..%5c..%5ccfg%5cconfig.json",
+            "/..\\..\\cfg\\config.json",
+            "/%5c..%5ccfg",
+            "/index.html%00.png",
+            "/index.html%0a",
+            "/%c0%ae%c0%ae/cfg/config.json", // Overlong UTF-8 for `.`
+            "/%ff",
+            "/%",
+            "/%2",
+            "/%zz",
+            "/%%32%65%%32%65/cfg",
+            "/C:/Windows/win.ini",
+            "/index.html::$DATA",
+            "/index.html.",
+            "/index.html%20",
+            "/...",
+        ];
+        for payload in payloads {
+            assert_eq!(resolve_static_path(payload), Err(PathRejection::Malformed), "{}", payload);
+        }
+    }
+
+    #[test]
+    fn rejects_hidden_and_backup_files() {
+        let payloads = [
+            "/.git/config",
+            "/.env",
+            "/%2egit/HEAD",
+            "/style/.DS_Store",
+            "/graphics/icon-mic.png~",
+            "/graphics/icon-mic.png%7e",
+            "/index.html~",
+            "/.well-known/../.git/config",
+        ];
+        for payload in payloads {
+            assert_eq!(resolve_static_path(payload), Err(PathRejection::Hidden), "{}", payload);
+        }
+    }
+
+    #[test]
+    fn decodes_only_once() {
+        // `%252e%252e` is the literal name `%2e%2e`, not `..`
+        assert_eq!(resolve_static_path("/%252e%252e/x"), Ok("%2e%2e/x".to_string()));
+        assert_eq!(normalize_static_path("%2e%2e/x"), Ok("%2e%2e/x".to_string()));
+        assert_eq!(normalize_static_path("/pages/50%off.html"), Ok("pages/50%off.html".to_string()));
+        assert_eq!(normalize_static_path("pages/../../x"), Err(PathRejection::Traversal));
+    }
+}
@@ -0,0 +1,224 @@
+// ----- Imports ----- //
+
+use std::{
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use serde_json::json;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time,
+};
+use tracing::info;
+
+use crate::sys_core::{HttpRequest, HttpResponse, core_responses::response_ok, core_shutdown::shutdown_requested};
+
+// ----- Global Pool Statistics ----- //
+
+static POOL_STATS: OnceLock<Arc<PoolStats>> = OnceLock::new();
+
+pub fn get_pool_stats() -> Option<&'static PoolStats> {
+    POOL_STATS.get().map(Arc::as_ref)
+}
+
+// ----- Structs ----- //
+
+/// Saturation counters for the connection pool.
+#[derive(Debug, Default)]
+pub struct PoolStats {
+    pub max_connections: usize,
+    pub queue_capacity: usize,
+    pub open: AtomicUsize,
+    pub queued: AtomicUsize,
+    pub accepted: AtomicU64,
+    pub rejected: AtomicU64,
+    pub completed: AtomicU64,
+}
+
+/// Caps how many connections are served at once. Each connection runs as its
+/// own task, so an idle chat waiting on the LLM costs memory, not a thread.
+/// Connections over the cap wait in a bounded accept queue; `try_enqueue`
+/// never waits, so when the queue is full too the caller sheds load.
+pub struct ConnectionPool {
+    permits: Arc<Semaphore>,
+    queue_capacity: usize,
+    queue_timeout: Duration,
+    stats: Arc<PoolStats>,
+}
+
+/// An accepted connection holding a slot, or a place in the accept queue
+/// until one frees up.
+pub struct QueuedConnection {
+    permits: Arc<Semaphore>,
+    ready: Option<OwnedSemaphorePermit>,
+    in_queue: bool,
+    timeout: Duration,
+    stats: Arc<PoolStats>,
+}
+
+/// One open connection's place in the pool, released on drop.
+pub struct ConnectionPermit {
+    _permit: OwnedSemaphorePermit,
+    stats: Arc<PoolStats>,
+}
+
+// ----- Implementations ----- //
+
+impl ConnectionPool {
+    pub fn new(max_connections: usize, queue_capacity: usize, queue_timeout: Duration) -> Self {
+        let max_connections = max_connections.max(1);
+        let stats = Arc::new(PoolStats {
+            max_connections,
+            queue_capacity,
+            ..Default::default()
+        });
+        let _ = POOL_STATS.set(Arc::clone(&stats));
+
+        info!("Up to {} open connections, accept queue of {}", max_connections, queue_capacity);
+
+        Self {
+            permits: Arc::new(Semaphore::new(max_connections)),
+            queue_capacity,
+            queue_timeout,
+            stats,
+        }
+    }
+
+    /// A free slot, or else a place in the accept queue. `None` once the
+    /// queue is full.
+    pub fn try_enqueue(&self) -> Option<QueuedConnection> {
+        let ready = Arc::clone(&self.permits).try_acquire_owned().ok();
+        let in_queue = ready.is_none();
+        if in_queue {
+            let joined = self.stats.queued.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                (queued < self.queue_capacity).then_some(queued + 1)
+            });
+            if joined.is_err() {
+                self.stats.rejected.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        }
+
+        Some(QueuedConnection {
+            permits: Arc::clone(&self.permits),
+            ready,
+            in_queue,
+            timeout: self.queue_timeout,
+            stats: Arc::clone(&self.stats),
+        })
+    }
+}
+
+impl QueuedConnection {
+    /// Wait for a slot. `None` when none freed up within the queue timeout,
+    /// or shutdown started first.
+    pub async fn wait(mut self) -> Option<ConnectionPermit> {
+        let permit = match self.ready.take() {
+            Some(permit) => Some(permit),
+            None => {
+                let acquire = Arc::clone(&self.permits).acquire_owned();
+                let permit = tokio::select! {
+                    permit = time::timeout(self.timeout, acquire) => permit.ok().and_then(Result::ok),
+                    _ = shutdown_requested() => None,
+                };
+                self.leave_queue();
+                permit
+            }
+        };
+
+        let Some(permit) = permit else {
+            self.stats.rejected.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        self.stats.open.fetch_add(1, Ordering::SeqCst);
+        self.stats.accepted.fetch_add(1, Ordering::Relaxed);
+        Some(ConnectionPermit {
+            _permit: permit,
+            stats: Arc::clone(&self.stats),
+        })
+    }
+
+    fn leave_queue(&mut self) {
+        if std::mem::take(&mut self.in_queue) {
+            self.stats.queued.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for QueuedConnection {
+    fn drop(&mut self) {
+        self.leave_queue();
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.stats.open.fetch_sub(1, Ordering::SeqCst);
+        self.stats.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// ----- Route Handlers ----- //
+
+/// GET /api/server/pool
+/// Returns the connection pool saturation counters.
+pub async fn handle_server_pool(_request: Arc<HttpRequest>) -> HttpResponse {
+    let json = match get_pool_stats() {
+        Some(stats) => json!({
+            "max_connections": stats.max_connections,
+            "queue_capacity": stats.queue_capacity,
+            "open": stats.open.load(Ordering::SeqCst),
+            "queued": stats.queued.load(Ordering::SeqCst),
+            "accepted": stats.accepted.load(Ordering::Relaxed),
+            "rejected": stats.rejected.load(Ordering::Relaxed),
+            "completed": stats.completed.load(Ordering::Relaxed),
+        }),
+        None => json!({ "error": "Connection pool not running" }),
+    };
+
+    response_ok(
+        "application/json; charset=utf-8",
+        json.to_string().into_bytes(),
+    )
+}
+
+// ----- Tests ----- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn queues_connections_over_the_cap_then_sheds_load() {
+        let pool = ConnectionPool::new(1, 1, Duration::from_millis(200));
+
+        let first = pool.try_enqueue().unwrap().wait().await.unwrap();
+        let second = pool.try_enqueue().unwrap();
+        assert!(pool.try_enqueue().is_none(), "queue of one is full");
+        assert_eq!(pool.stats.queued.load(Ordering::SeqCst), 1);
+
+        // A queued connection gets the slot once it frees up
+        let waiting = tokio::spawn(second.wait());
+        drop(first);
+        let second = waiting.await.unwrap().unwrap();
+        assert_eq!(pool.stats.queued.load(Ordering::SeqCst), 0);
+
+        // ...and gives up when none does in time
+        let started = tokio::time::Instant::now();
+        assert!(pool.try_enqueue().unwrap().wait().await.is_none());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        // Leaving the queue without waiting frees the place
+        drop(pool.try_enqueue().unwrap());
+        assert_eq!(pool.stats.queued.load(Ordering::SeqCst), 0);
+
+        drop(second);
+        assert_eq!(pool.stats.open.load(Ordering::SeqCst), 0);
+        assert_eq!(pool.stats.accepted.load(Ordering::Relaxed), 2);
+        assert_eq!(pool.stats.rejected.load(Ordering::Relaxed), 2);
+    }
+}
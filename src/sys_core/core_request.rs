@@ -0,0 +1,440 @@
+// ----- Imports ----- //
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt},
+    time,
+};
+
+use crate::sys_core::core_config::ServerConfig;
+
+// ----- Limits ----- //
+
+const MAX_CHUNK_LINE_BYTES: usize = 1024;
+const REQUEST_LINE_OVERHEAD: usize = 32; // Method, version, spaces and CRLF around the URI
+const BODY_READ_STEP: usize = 16 * 1024; // Each step gets its own read timeout
+
+/// Size and time limits for reading one request, from the `"server"` config.
+pub struct RequestLimits {
+    pub max_header_bytes: usize,
+    pub max_body_bytes: usize,
+    pub max_uri_bytes: usize,
+    /// Longest stall on a single read.
+    pub read_timeout: Duration,
+    /// The whole request must have arrived by then, however slowly it trickles in.
+    pub deadline: Instant,
+}
+
+// ----- Structs ----- //
+
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Path parameters captured by the router (`{name}` segments).
+    pub params: HashMap<String, String>,
+    /// Pattern of the route that matched, e.g. `/api/session/{id}`.
+    pub route: Option<&'static str>,
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    ConnectionClosed,
+    Io(io::Error),
+    Malformed(&'static str),
+    HeadersTooLarge,
+    BodyTooLarge,
+    UriTooLong,
+    UnsupportedEncoding,
+    TimedOut,
+}
+
+// ----- Implementations ----- //
+
+impl HttpRequest {
+    /// Case-insensitive header lookup. Returns the first matching value.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Body as UTF-8 text. Invalid UTF-8 yields an empty string so JSON
+    /// handlers fall back to their defaults instead of parsing garbage.
+    pub fn body_str(&self) -> &str {
+        std::str::from_utf8(&self.body).unwrap_or("")
+    }
+
+    /// Whether the client wants the connection kept open after this request.
+    /// HTTP/1.1 defaults to keep-alive, HTTP/1.0 has to ask for it.
+    pub fn wants_keep_alive(&self) -> bool {
+        let connection = self.header("Connection").unwrap_or("");
+        let has_token = |token: &str| {
+            connection
+                .split(',')
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        };
+
+        if self.version == "HTTP/1.0" {
+            has_token("keep-alive")
+        } else {
+            !has_token("close")
+        }
+    }
+
+    /// Path parameter captured by the router.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+
+    /// Percent-decoded value of a query string parameter.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |s: &str| percent_decode(&s.replace('+', " "));
+            (decode(key) == name).then(|| decode(value))
+        })
+    }
+}
+
+impl RequestLimits {
+    /// Limits for a request whose first byte just arrived.
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            max_header_bytes: config.max_header_bytes,
+            max_body_bytes: config.max_body_bytes,
+            max_uri_bytes: config.max_uri_bytes,
+            read_timeout: Duration::from_secs(config.read_timeout_secs),
+            deadline: Instant::now() + Duration::from_secs(config.request_timeout_secs),
+        }
+    }
+
+    /// Run one read, bounded by the read timeout and whatever is left
+    /// before the deadline.
+    async fn timed<T>(&self, read: impl Future<Output = io::Result<T>>) -> Result<T, RequestError> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        match time::timeout(remaining.min(self.read_timeout), read).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(RequestError::TimedOut),
+        }
+    }
+}
+
+impl From<io::Error> for RequestError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => RequestError::ConnectionClosed,
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => RequestError::TimedOut,
+            _ => RequestError::Io(e),
+        }
+    }
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::ConnectionClosed => write!(f, "connection closed"),
+            RequestError::Io(e) => write!(f, "I/O error: {}", e),
+            RequestError::Malformed(what) => write!(f, "malformed request: {}", what),
+            RequestError::HeadersTooLarge => write!(f, "request headers too large"),
+            RequestError::BodyTooLarge => write!(f, "request body too large"),
+            RequestError::UriTooLong => write!(f, "request URI too long"),
+            RequestError::UnsupportedEncoding => write!(f, "unsupported transfer encoding"),
+            RequestError::TimedOut => write!(f, "request not received in time"),
+        }
+    }
+}
+
+// ----- Parsing ----- //
+
+/// Read one HTTP/1.1 request from the reader: request line, headers and a body
+/// framed by either `Content-Length` or `Transfer-Encoding: chunked`.
+pub async fn read_request<R>(reader: &mut R, limits: &RequestLimits) -> Result<HttpRequest, RequestError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    // Request line (tolerate stray CRLFs between requests, RFC 9112 §2.2)
+    let mut line_budget = limits.max_uri_bytes + REQUEST_LINE_OVERHEAD;
+    let mut request_line = String::new();
+    while request_line.is_empty() {
+        match read_line(reader, &mut line_budget, limits).await {
+            Ok(Some(line)) => request_line = line,
+            Ok(None) => return Err(RequestError::ConnectionClosed),
+            Err(RequestError::HeadersTooLarge) => return Err(RequestError::UriTooLong),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().ok_or(RequestError::Malformed("request line"))?;
+    let target = parts.next().ok_or(RequestError::Malformed("request line"))?;
+    let version = parts.next().unwrap_or("HTTP/1.0");
+    if !version.starts_with("HTTP/1.") {
+        return Err(RequestError::Malformed("HTTP version"));
+    }
+    if target.len() > limits.max_uri_bytes {
+        return Err(RequestError::UriTooLong);
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    // Headers
+    let mut budget = limits.max_header_bytes;
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader, &mut budget, limits).await?.ok_or(RequestError::ConnectionClosed)?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(RequestError::Malformed("header line"))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = HttpRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        version: version.to_string(),
+        headers,
+        body: Vec::new(),
+        params: HashMap::new(),
+        route: None,
+    };
+
+    // Body
+    if let Some(encoding) = request.header("Transfer-Encoding") {
+        if !encoding.eq_ignore_ascii_case("chunked") {
+            return Err(RequestError::UnsupportedEncoding);
+        }
+        request.body = read_chunked_body(reader, limits).await?;
+    } else if let Some(length) = request.header("Content-Length") {
+        let length: usize = length
+            .parse()
+            .map_err(|_| RequestError::Malformed("Content-Length"))?;
+        if length > limits.max_body_bytes {
+            return Err(RequestError::BodyTooLarge);
+        }
+        let mut body = vec![0; length];
+        read_exact(reader, &mut body, limits).await?;
+        request.body = body;
+    }
+
+    Ok(request)
+}
+
+async fn read_chunked_body<R>(reader: &mut R, limits: &RequestLimits) -> Result<Vec<u8>, RequestError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut body = Vec::new();
+
+    loop {
+        let mut budget = MAX_CHUNK_LINE_BYTES;
+        let size_line = read_line(reader, &mut budget, limits).await?.ok_or(RequestError::ConnectionClosed)?;
+
+        // Ignore chunk extensions (";name=value")
+        let size_str = size_line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_str, 16)
+            .map_err(|_| RequestError::Malformed("chunk size"))?;
+
+        if size == 0 {
+            break;
+        }
+        // A huge size would overflow `body.len() + size`
+        if size > limits.max_body_bytes.saturating_sub(body.len()) {
+            return Err(RequestError::BodyTooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        read_exact(reader, &mut body[start..], limits).await?;
+
+        // Each chunk is terminated by CRLF
+        let mut crlf = [0u8; 2];
+        limits.timed(reader.read_exact(&mut crlf)).await?;
+        if &crlf != b"\r\n" {
+            return Err(RequestError::Malformed("chunk terminator"));
+        }
+    }
+
+    // Discard trailer fields up to the terminating empty line
+    let mut budget = limits.max_header_bytes;
+    while let Some(line) = read_line(reader, &mut budget, limits).await? {
+        if line.is_empty() {
+            break;
+        }
+    }
+
+    Ok(body)
+}
+
+/// `read_exact` in steps, so a large body only has to keep moving rather
+/// than arrive within a single read timeout.
+async fn read_exact<R>(reader: &mut R, buf: &mut [u8], limits: &RequestLimits) -> Result<(), RequestError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    for step in buf.chunks_mut(BODY_READ_STEP) {
+        limits.timed(reader.read_exact(step)).await?;
+    }
+    Ok(())
+}
+
+/// Read a single CRLF (or bare LF) terminated line, charging its length against
+/// `budget`. Returns `None` on a clean EOF before any bytes were read.
+async fn read_line<R>(
+    reader: &mut R,
+    budget: &mut usize,
+    limits: &RequestLimits,
+) -> Result<Option<String>, RequestError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = Vec::new();
+    let mut limited = (&mut *reader).take(*budget as u64 + 1);
+    let read = limits.timed(limited.read_until(b'\n', &mut line)).await?;
+
+    if read == 0 {
+        return Ok(None);
+    }
+    if read > *budget {
+        return Err(RequestError::HeadersTooLarge);
+    }
+    if !line.ends_with(b"\n") {
+        return Err(RequestError::ConnectionClosed);
+    }
+    *budget -= read;
+
+    while line.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| RequestError::Malformed("non UTF-8 header"))
+}
+
+// ----- Helpers ----- //
+
+/// Decode `%XX` escapes from a URL component. Invalid escapes are kept as-is.
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => match (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    out.push(hi << 4 | lo);
+                    i += 3;
+                    continue;
+                }
+                _ => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+// ----- Tests ----- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_body_bytes: usize) -> RequestLimits {
+        RequestLimits::new(&ServerConfig {
+            max_body_bytes,
+            ..ServerConfig::default()
+        })
+    }
+
+    async fn parse(raw: &[u8], max_body_bytes: usize) -> Result<HttpRequest, RequestError> {
+        let mut reader = raw;
+        read_request(&mut reader, &limits(max_body_bytes)).await
+    }
+
+    #[tokio::test]
+    async fn reads_content_length_body() {
+        let raw = b"POST /api/x?a=1 HTTP/1.1\r\nHost: h\r\nContent-Length: 5\r\n\r\nhelloEXTRA";
+        let request = parse(raw, 1024).await.unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/x");
+        assert_eq!(request.query, "a=1");
+        assert_eq!(request.header("host"), Some("h"));
+        assert_eq!(request.body, b"hello");
+    }
+
+    #[tokio::test]
+    async fn reads_chunked_body_with_extensions_and_trailers() {
+        let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5;name=value\r\nhello\r\n6\r\n world\r\n0\r\nTrailer: x\r\n\r\n";
+        let request = parse(raw, 1024).await.unwrap();
+
+        assert_eq!(request.body, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn rejects_oversize_bodies() {
+        let raw = b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world";
+        assert!(matches!(parse(raw, 10).await, Err(RequestError::BodyTooLarge)));
+
+        let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n";
+        assert!(matches!(parse(raw, 10).await, Err(RequestError::BodyTooLarge)));
+    }
+
+    #[tokio::test]
+    async fn rejects_chunk_sizes_that_would_overflow() {
+        let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\nffffffffffffffff\r\n";
+        assert!(matches!(parse(raw, 1024).await, Err(RequestError::BodyTooLarge)));
+
+        let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nfffffffffffffffff\r\n";
+        assert!(matches!(parse(raw, 1024).await, Err(RequestError::Malformed("chunk size"))));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_bodies() {
+        let cases: [(&[u8], &str); 4] = [
+            (b"POST / HTTP/1.1\r\nContent-Length: abc\r\n\r\n", "Content-Length"),
+            (b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n", "Content-Length"),
+            (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n", "chunk size"),
+            (b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhelloXX0\r\n\r\n", "chunk terminator"),
+        ];
+        for (raw, what) in cases {
+            match parse(raw, 1024).await {
+                Err(RequestError::Malformed(found)) => assert_eq!(found, what),
+                other => panic!("expected malformed {}, got {:?}", what, other.map(|r| r.body)),
+            }
+        }
+
+        let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n";
+        assert!(matches!(parse(raw, 1024).await, Err(RequestError::UnsupportedEncoding)));
+    }
+
+    #[tokio::test]
+    async fn reports_truncated_bodies_as_closed() {
+        let raw = b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello";
+        assert!(matches!(parse(raw, 1024).await, Err(RequestError::ConnectionClosed)));
+    }
+}
@@ -1,28 +1,37 @@
 // ----- Imports ----- //
 
-use crate::sys_core::core_routing::HttpResponse;
+use serde_json::json;
+
+use crate::sys_core::{StatusCode, core_routing::HttpResponse};
 
 // ----- Responses ----- //
-pub fn response_ok(content_type: &'static str, body: Vec<u8>) -> HttpResponse {
-    HttpResponse {
-        status_line: "HTTP/1.1 200 OK",
-        content_type,
-        body,
-    }
+pub fn response_ok(content_type: &str, body: Vec<u8>) -> HttpResponse {
+    HttpResponse::new(StatusCode::Ok).with_body(content_type, body)
+}
+
+pub fn response_json(value: &serde_json::Value) -> HttpResponse {
+    HttpResponse::json(StatusCode::Ok, value)
+}
+
+/// JSON error body `{ "error": msg }` with the given status.
+pub fn response_error(status: StatusCode, msg: &str) -> HttpResponse {
+    HttpResponse::json(status, &json!({ "error": msg }))
 }
 
 pub fn response_not_found(msg: &str) -> HttpResponse {
-    HttpResponse {
-        status_line: "HTTP/1.1 404 NOT FOUND",
-        content_type: "text/plain; charset=utf-8",
-        body: msg.as_bytes().to_vec(),
-    }
+    response_status(StatusCode::NotFound, msg)
 }
 
-pub fn response_method_not_allowed() -> HttpResponse {
-    HttpResponse {
-        status_line: "HTTP/1.1 405 METHOD NOT ALLOWED",
-        content_type: "text/plain; charset=utf-8",
-        body: b"Method not allowed".to_vec(),
-    }
-}
\ No newline at end of file
+pub fn response_method_not_allowed(allowed: &[&str]) -> HttpResponse {
+    response_status(StatusCode::MethodNotAllowed, "Method not allowed")
+        .with_header("Allow", allowed.join(", "))
+}
+
+pub fn response_status(status: StatusCode, msg: &str) -> HttpResponse {
+    HttpResponse::new(status).with_body("text/plain; charset=utf-8", msg.as_bytes().to_vec())
+}
+
+pub fn response_service_unavailable(retry_after_secs: u64) -> HttpResponse {
+    response_status(StatusCode::ServiceUnavailable, "Server busy, try again shortly")
+        .with_header("Retry-After", retry_after_secs.to_string())
+}
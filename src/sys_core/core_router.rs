@@ -0,0 +1,257 @@
+// ----- Imports ----- //
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+};
+
+use crate::sys_core::{HttpRequest, HttpResponse, core_request::percent_decode, core_websocket::WebSocket};
+
+// ----- Types ----- //
+
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// An `async fn(Arc<HttpRequest>) -> HttpResponse`, boxed by the router.
+pub type RouteHandler = Arc<dyn Fn(Arc<HttpRequest>) -> BoxFuture<HttpResponse> + Send + Sync>;
+
+/// Runs after the handshake and owns the connection until it returns.
+pub type WebSocketHandler = Arc<dyn Fn(Arc<HttpRequest>, WebSocket) -> BoxFuture<()> + Send + Sync>;
+
+// ----- Global Router ----- //
+
+static ROUTER: OnceLock<Router> = OnceLock::new();
+
+pub fn init_router(router: Router) {
+    if ROUTER.set(router).is_err() {
+        panic!("Router already initialized");
+    }
+}
+
+pub fn get_router() -> &'static Router {
+    ROUTER.get().expect("Router not initialized")
+}
+
+// ----- Structs ----- //
+
+/// Routing table keyed by method and path pattern. Patterns are `/`-separated
+/// segments where `{name}` captures one segment into `HttpRequest::params`.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+struct Route {
+    method: &'static str,
+    pattern: &'static str,
+    segments: Vec<Segment>,
+    handler: Handler,
+}
+
+enum Handler {
+    Http(RouteHandler),
+    WebSocket(WebSocketHandler),
+}
+
+enum Segment {
+    Literal(&'static str),
+    Param(&'static str),
+}
+
+pub enum RouteMatch {
+    Found(RouteHandler),
+    /// A WebSocket endpoint, answered by the handshake rather than a handler.
+    Upgrade(WebSocketHandler),
+    MethodNotAllowed(Vec<&'static str>),
+    NotFound,
+}
+
+// ----- Implementations ----- //
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<F, Fut>(&mut self, method: &'static str, pattern: &'static str, handler: F) -> &mut Self
+    where
+        F: Fn(Arc<HttpRequest>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HttpResponse> + Send + 'static,
+    {
+        let handler: RouteHandler = Arc::new(move |request| Box::pin(handler(request)));
+        self.push(method, pattern, Handler::Http(handler))
+    }
+
+    /// WebSocket endpoint on `pattern`, reached with a GET upgrade request.
+    pub fn websocket<F, Fut>(&mut self, pattern: &'static str, handler: F) -> &mut Self
+    where
+        F: Fn(Arc<HttpRequest>, WebSocket) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handler: WebSocketHandler = Arc::new(move |request, socket| Box::pin(handler(request, socket)));
+        self.push("GET", pattern, Handler::WebSocket(handler))
+    }
+
+    fn push(&mut self, method: &'static str, pattern: &'static str, handler: Handler) -> &mut Self {
+        let segments = split_path(pattern)
+            .map(|s| match s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                Some(name) => Segment::Param(name),
+                None => Segment::Literal(s),
+            })
+            .collect();
+
+        self.routes.push(Route {
+            method,
+            pattern,
+            segments,
+            handler,
+        });
+        self
+    }
+
+    pub fn get<F, Fut>(&mut self, pattern: &'static str, handler: F) -> &mut Self
+    where
+        F: Fn(Arc<HttpRequest>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HttpResponse> + Send + 'static,
+    {
+        self.add("GET", pattern, handler)
+    }
+
+    pub fn post<F, Fut>(&mut self, pattern: &'static str, handler: F) -> &mut Self
+    where
+        F: Fn(Arc<HttpRequest>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HttpResponse> + Send + 'static,
+    {
+        self.add("POST", pattern, handler)
+    }
+
+    /// Resolve the request against the table. On a match the route pattern
+    /// and captured path parameters are stored on the request. `HEAD` falls
+    /// back to the `GET` handler; the server then sends only the headers.
+    /// When the path exists under other methods the caller gets the list for
+    /// the `Allow` header.
+    pub fn dispatch(&self, request: &mut HttpRequest) -> RouteMatch {
+        let mut head_fallback = None;
+
+        for route in &self.routes {
+            let Some(params) = route.match_path(&request.path) else {
+                continue;
+            };
+
+            if route.method == request.method {
+                request.params = params;
+                request.route = Some(route.pattern);
+                return match &route.handler {
+                    Handler::Http(handler) => RouteMatch::Found(Arc::clone(handler)),
+                    Handler::WebSocket(handler) => RouteMatch::Upgrade(Arc::clone(handler)),
+                };
+            }
+            if request.method == "HEAD"
+                && route.method == "GET"
+                && let Handler::Http(handler) = &route.handler
+                && head_fallback.is_none()
+            {
+                head_fallback = Some((route.pattern, params, Arc::clone(handler)));
+            }
+        }
+
+        if let Some((pattern, params, handler)) = head_fallback {
+            request.params = params;
+            request.route = Some(pattern);
+            return RouteMatch::Found(handler);
+        }
+
+        let allowed = self.methods_for(&request.path);
+        if allowed.is_empty() {
+            RouteMatch::NotFound
+        } else {
+            RouteMatch::MethodNotAllowed(allowed)
+        }
+    }
+
+    /// Every method registered for a path, e.g. for `Allow` on `OPTIONS`,
+    /// with `HEAD` wherever `GET` is.
+    pub fn methods_for(&self, path: &str) -> Vec<&'static str> {
+        let mut methods = Vec::new();
+        for route in &self.routes {
+            if route.match_path(path).is_some() && !methods.contains(&route.method) {
+                methods.push(route.method);
+            }
+        }
+        if methods.contains(&"GET") && !methods.contains(&"HEAD") {
+            methods.push("HEAD");
+        }
+        methods
+    }
+}
+
+impl Route {
+    fn match_path(&self, path: &str) -> Option<HashMap<String, String>> {
+        let mut params = HashMap::new();
+        let mut parts = split_path(path);
+
+        for segment in &self.segments {
+            let part = parts.next()?;
+            match segment {
+                Segment::Literal(lit) if *lit == part => {}
+                Segment::Literal(_) => return None,
+                Segment::Param(name) => {
+                    params.insert(name.to_string(), percent_decode(part));
+                }
+            }
+        }
+
+        // Every request segment must be consumed
+        parts.next().is_none().then_some(params)
+    }
+}
+
+// ----- Helpers ----- //
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+// ----- Tests ----- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys_core::core_responses::response_ok;
+
+    async fn ok(_request: Arc<HttpRequest>) -> HttpResponse {
+        response_ok("text/plain", b"ok".to_vec())
+    }
+
+    fn request(method: &str, path: &str) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: String::new(),
+            version: "HTTP/1.1".to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            params: HashMap::new(),
+            route: None,
+        }
+    }
+
+    #[test]
+    fn head_falls_back_to_get_routes() {
+        let mut router = Router::new();
+        router.get("/api/items/{id}", ok).post("/api/items", ok);
+
+        let mut head = request("HEAD", "/api/items/a%20b");
+        assert!(matches!(router.dispatch(&mut head), RouteMatch::Found(_)));
+        assert_eq!(head.route, Some("/api/items/{id}"));
+        assert_eq!(head.params.get("id").map(String::as_str), Some("a b"));
+
+        let mut head = request("HEAD", "/api/items");
+        match router.dispatch(&mut head) {
+            RouteMatch::MethodNotAllowed(allowed) => assert_eq!(allowed, ["POST"]),
+            _ => panic!("HEAD on a POST-only route should be refused"),
+        }
+        assert_eq!(router.methods_for("/api/items/1"), ["GET", "HEAD"]);
+    }
+}
@@ -1,125 +1,231 @@
 // ----- Imports ----- //
 
-use std::path::Path;
-use std::sync::Arc;
-
-use crate::sys_console::handle_api_command;
-use crate::sys_core::core_responses::{response_not_found, response_ok};
-use crate::sys_dashboard::dashboard_handlers::{
-    handle_dashboard_sessions_by_day, handle_dashboard_solutions, handle_dashboard_stats,
-    handle_dashboard_tags, handle_dashboard_top_companies,
-};
+use std::{sync::Arc, time::Instant};
+
+use tokio::sync::mpsc;
+use tracing::Span;
+
+use crate::sys_core::{HttpRequest, StatusCode};
+use crate::sys_core::core_cors::{apply_cors, handle_options};
+use crate::sys_core::core_encoding::encode_response;
+use crate::sys_core::core_limits::handle_server_limits;
+use crate::sys_core::core_metrics::{Counter, Histogram, handle_metrics};
+use crate::sys_core::core_pool::handle_server_pool;
+use crate::sys_core::core_responses::{response_method_not_allowed, response_not_found, response_status};
+use crate::sys_core::core_router::{Router, RouteMatch, get_router};
+use crate::sys_core::core_static::{handle_server_cache, serve_static};
 use crate::sys_resource::CachedLoader;
-use crate::sys_session::session_handlers::{
-    handle_session_get, handle_session_get_artifact, handle_session_list_artifacts,
-    handle_session_sendinput, handle_session_start,
-};
+
+// ----- Constants ----- //
+
+const STREAM_BUFFER_CHUNKS: usize = 16; // Producers wait once the client falls this far behind
+
+// ----- Metrics ----- //
+
+static HTTP_REQUESTS: Counter = Counter::new(
+    "charmline_http_requests_total",
+    "Requests answered, by method, route pattern and status.",
+    &["method", "route", "status"],
+);
+
+static HTTP_REQUEST_SECONDS: Histogram = Histogram::new(
+    "charmline_http_request_duration_seconds",
+    "Time until the response headers were ready, by route pattern and status.",
+    &["route", "status"],
+);
+
+// ----- Types ----- //
+
+/// Pieces of a response body produced after the headers are sent.
+pub type StreamBody = mpsc::Receiver<Vec<u8>>;
+
+/// Producer side of a `StreamBody`. Sends fail once the client is gone.
+pub type BodySender = mpsc::Sender<Vec<u8>>;
 
 // ----- Structs ----- //
 
 pub struct HttpResponse {
-    pub status_line: &'static str,
-    pub content_type: &'static str,
+    pub status: StatusCode,
+    pub content_type: String,
+    pub headers: Vec<(String, String)>,
     pub body: Vec<u8>,
+    /// Streamed body, sent with chunked transfer encoding instead of `body`.
+    pub stream: Option<StreamBody>,
 }
 
 // ----- Implementations ----- //
 
 impl HttpResponse {
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let header = format!(
-            "{}\r\nContent-Length: {}\r\nContent-Type: {}\r\n\r\n",
-            self.status_line,
-            self.body.len(),
-            self.content_type
-        );
-        let mut response = header.into_bytes();
-        response.extend_from_slice(&self.body);
-        response
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8".to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            stream: None,
+        }
     }
-}
 
-// ----- Routing ----- //
+    /// 200 whose body is fed through the returned sender, e.g. Server-Sent
+    /// Events. Each piece reaches the client as soon as it is sent; the body
+    /// ends when the sender is dropped.
+    pub fn streaming(content_type: &str) -> (Self, BodySender) {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+        let mut response = Self::new(StatusCode::Ok);
+        response.content_type = content_type.to_string();
+        response.stream = Some(receiver);
+        (response, sender)
+    }
 
-pub fn handle_route(path: &str, loader: &Arc<CachedLoader>, body: &str) -> HttpResponse {
-    // Strip query parameters (anything after '?')
-    let clean_path = path.split('?').next().unwrap_or("/");
-
-    match clean_path {
-        "/" => match loader.load("index.html") {
-            Some(file) => response_ok("text/html", file.bytes),
-            None => response_not_found("index.html not found"),
-        },
-        "/api/cmd" => handle_api_command(body),
-        "/api/session/start" => handle_session_start(),
-        "/api/session/get" => handle_session_get(body),
-        "/api/session/sendinput" => handle_session_sendinput(body),
-        "/api/session/listartifacts" => handle_session_list_artifacts(body),
-        "/api/session/getartifact" => handle_session_get_artifact(body),
-        "/api/dashboard/stats" => handle_dashboard_stats(),
-        "/api/dashboard/top_companies" => handle_dashboard_top_companies(body),
-        "/api/dashboard/tags" => handle_dashboard_tags(),
-        "/api/dashboard/solutions" => handle_dashboard_solutions(),
-        "/api/dashboard/sessions_by_day" => handle_dashboard_sessions_by_day(body),
-        _ => serve_static(clean_path, loader),
+    /// Serialize a JSON value as the body.
+    pub fn json(status: StatusCode, value: &serde_json::Value) -> Self {
+        Self::new(status).with_body("application/json; charset=utf-8", value.to_string().into_bytes())
     }
-}
 
-fn serve_static(path: &str, loader: &Arc<CachedLoader>) -> HttpResponse {
+    pub fn with_body(mut self, content_type: &str, body: Vec<u8>) -> Self {
+        self.content_type = content_type.to_string();
+        self.body = body;
+        self
+    }
 
-    // Strip query params again for safety in direct calls
-    let path = path.split('?').next().unwrap_or("").trim_start_matches('/');
+    /// Append a header. Repeats are kept, which `Set-Cookie` relies on.
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
 
-    // Compute absolute path relative to loader base
-    let full_path = loader.root_dir.join(path);
+    /// Replace every existing value of a header with a single one.
+    pub fn set_header(&mut self, name: &str, value: impl Into<String>) {
+        self.headers.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.into()));
+    }
 
-    if path.contains("..") {
-        println!(
-            "[serve_static] Rejected invalid path with '..' → {}",
-            full_path.display()
-        );
-        return response_not_found("Invalid path");
+    /// Add a field name to `Vary`, keeping any already listed.
+    pub fn add_vary(&mut self, field: &str) {
+        let vary = match self.header("Vary") {
+            Some(v) if v.split(',').any(|f| f.trim().eq_ignore_ascii_case(field)) => return,
+            Some(v) => format!("{}, {}", v, field),
+            None => field.to_string(),
+        };
+        self.set_header("Vary", vary);
     }
 
-    let content_type = content_type_for(path);
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
 
+    pub fn to_bytes(&self, keep_alive: bool) -> Vec<u8> {
+        let mut response = self.head_bytes(keep_alive);
+        if self.status.allows_body() {
+            response.extend_from_slice(&self.body);
+        }
+        response
+    }
+
+    /// Status line and headers only, the answer to a `HEAD` request: the
+    /// same `Content-Length` a `GET` gets, without the body.
+    pub fn head_bytes(&self, keep_alive: bool) -> Vec<u8> {
+        let mut header = format!("HTTP/1.1 {} {}\r\n", self.status.code(), self.status.reason());
+        if self.status.allows_body() {
+            let length = match self.stream {
+                Some(_) => "Transfer-Encoding: chunked".to_string(),
+                None => format!("Content-Length: {}", self.body.len()),
+            };
+            header.push_str(&format!("{}\r\nContent-Type: {}\r\n", length, self.content_type));
+        }
+        // Protocol upgrades set their own `Connection: Upgrade`
+        if self.header("Connection").is_none() {
+            header.push_str(&format!(
+                "Connection: {}\r\n",
+                if keep_alive { "keep-alive" } else { "close" }
+            ));
+        }
+        for (name, value) in &self.headers {
+            header.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        header.push_str("\r\n");
+        header.into_bytes()
+    }
+}
+
+// ----- Routing ----- //
 
-    // Try direct file first
-    if let Some(file) = loader.load(path) {
-        return response_ok(content_type, file.bytes);
-    } 
+/// Routes owned by sys_core itself.
+pub fn register_routes(router: &mut Router) {
+    router
+        .get("/metrics", handle_metrics)
+        .get("/api/server/pool", handle_server_pool)
+        .get("/api/server/cache", handle_server_cache)
+        .get("/api/server/limits", handle_server_limits);
+}
 
-    // Fallback: /pages/{path}.html if no extension present
-    if !path.contains('.') {
-        let html_path = format!("pages/{}.html", path);
+/// Dispatch through the routing table. Paths outside `/api/` that no module
+/// registered fall through to static files, which only answer GET. `OPTIONS`
+/// is answered here for every path, and CORS headers and compression are
+/// applied on the way out.
+pub async fn handle_route(mut request: HttpRequest, loader: &Arc<CachedLoader>) -> HttpResponse {
+    let started = Instant::now();
+    if request.method == "OPTIONS" {
+        let response = handle_options(&request);
+        observe_request(&request, &response, started);
+        return response;
+    }
 
-        let content_type = "text/html; charset=utf-8";
+    // Path parameters are stored first, then the request is shared with the handler
+    let route = get_router().dispatch(&mut request);
+    if let Some(pattern) = request.route {
+        Span::current().record("route", pattern);
+    }
+    let request = Arc::new(request);
 
-        if let Some(file) = loader.load(&html_path) {
-     
-            return response_ok(content_type, file.bytes);
-        }
+    let mut response = dispatch(route, &request, loader).await;
+    apply_cors(&request, &mut response);
+    if request.route.is_some() {
+        encode_response(&request, &mut response).await;
     }
+    observe_request(&request, &response, started);
+    response
+}
 
-    println!("[serve_static] File not found at: {}", full_path.display());
-    response_not_found("File not found")
+/// Labelled by route pattern rather than path, so IDs and static file names
+/// do not each get their own series. Likewise any method a client makes up
+/// counts as `other`.
+fn observe_request(request: &HttpRequest, response: &HttpResponse, started: Instant) {
+    let route = match request.route {
+        Some(pattern) => pattern,
+        None if request.path.starts_with("/api/") => "unmatched",
+        None => "static",
+    };
+    let method = match request.method.as_str() {
+        method @ ("GET" | "HEAD" | "POST" | "PUT" | "PATCH" | "DELETE" | "OPTIONS") => method,
+        _ => "other",
+    };
+    let status = response.status.code().to_string();
+    HTTP_REQUESTS.inc(&[method, route, &status]);
+    HTTP_REQUEST_SECONDS.observe(&[route, &status], started.elapsed());
 }
 
-// ----- Helpers ----- //
-
-fn content_type_for(path: &str) -> &'static str {
-    match Path::new(path)
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("")
-    {
-        "html" => "text/html; charset=utf-8",
-        "css" => "text/css; charset=utf-8",
-        "js" => "application/javascript; charset=utf-8",
-        "jpg" | "jpeg" => "image/jpeg",
-        "png" => "image/png",
-        "svg" => "image/svg+xml",
-        "ico" => "image/x-icon",
-        _ => "application/octet-stream",
+async fn dispatch(route: RouteMatch, request: &Arc<HttpRequest>, loader: &Arc<CachedLoader>) -> HttpResponse {
+    match route {
+        RouteMatch::Found(handler) => handler(Arc::clone(request)).await,
+        RouteMatch::Upgrade(_) => response_status(StatusCode::UpgradeRequired, "WebSocket endpoint")
+            .with_header("Upgrade", "websocket"),
+        RouteMatch::MethodNotAllowed(allowed) => response_method_not_allowed(&allowed),
+        RouteMatch::NotFound if request.path.starts_with("/api/") => {
+            response_not_found("Unknown API route")
+        }
+        RouteMatch::NotFound if !matches!(request.method.as_str(), "GET" | "HEAD") => {
+            response_method_not_allowed(&["GET", "HEAD"])
+        }
+        RouteMatch::NotFound => {
+            // File reads and compression block, so they run off the async workers
+            let (request, loader, span) = (Arc::clone(request), Arc::clone(loader), Span::current());
+            tokio::task::spawn_blocking(move || span.in_scope(|| serve_static(&request, &loader)))
+                .await
+                .unwrap_or_else(|_| response_status(StatusCode::InternalServerError, "Static file error"))
+        }
     }
 }
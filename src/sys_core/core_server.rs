@@ -1,84 +1,489 @@
 // ----- Imports ----- //
 
 use std::{
-    io::{Read, Write},
-    net::{TcpListener, TcpStream},
+    io,
     sync::Arc,
-    thread,
+    time::{Duration, Instant},
 };
 
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    task::JoinSet,
+    time,
+};
+use tracing::{Instrument, Span, debug, error, info, warn};
+
 use crate::{
-    sys_core::{core_responses::response_method_not_allowed, core_routing::handle_route},
-    sys_resource::CachedLoader,
+    sys_core::{
+        core_config::ListenerConfig,
+        core_limits::{IpSlot, Rejection, acquire_ip_slot, count_rejection},
+        core_listener::{Accepted, Listener},
+        core_pool::{ConnectionPermit, ConnectionPool},
+        core_request::{RequestError, RequestLimits, read_request},
+        core_responses::{response_service_unavailable, response_status},
+        core_router::WebSocketHandler,
+        core_routing::handle_route,
+        core_shutdown::{is_shutting_down, shutdown_requested, track_active},
+        core_stream::{ChunkedWriter, ClientStream, write_timed},
+        core_tls::TlsAcceptor,
+        core_tracing::{REQUEST_ID_HEADER, request_id_for, request_span},
+        core_websocket::{WebSocket, handshake, is_upgrade_request},
+        get_config, HttpRequest, HttpResponse, StatusCode,
+    },
+    sys_resource::{CachedLoader, EMBEDDED},
 };
 
+// ----- Constants ----- //
+
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100); // e.g. out of file descriptors
+const REJECT_TIMEOUT: Duration = Duration::from_millis(500);
+
 // ----- Structs ----- //
 
 pub struct Server {
-    address: String,
+    listeners: Vec<ListenerConfig>,
     loader: Arc<CachedLoader>,
 }
 
+/// An accepted connection with its place in the pool and its client's slot
+/// against the per-IP limit, both held until the connection ends.
+struct Connection {
+    accepted: Accepted,
+    slot: Option<IpSlot>,
+    permit: ConnectionPermit,
+}
+
+/// A completed WebSocket handshake, handed from the request loop to the
+/// socket handler along with the span of the request that opened it.
+struct Upgrade {
+    request: HttpRequest,
+    handler: WebSocketHandler,
+    span: Span,
+}
+
 // ----- Implementations ----- //
 
 impl Server {
-    pub fn new(address: &str, base_dir: &str) -> Self {
+    pub fn new(listeners: Vec<ListenerConfig>, base_dir: &str) -> Self {
         use std::fs;
         use std::path::PathBuf;
 
-        // Resolve to absolute path for clarity in logs
-        let full_path = fs::canonicalize(base_dir).unwrap_or_else(|_| PathBuf::from(base_dir));
+        let static_config = &get_config().static_files;
+        let loader = if EMBEDDED {
+            CachedLoader::embedded(static_config.override_dir.as_deref())
+        } else {
+            // Resolve to absolute path for clarity in logs
+            let full_path = fs::canonicalize(base_dir).unwrap_or_else(|_| PathBuf::from(base_dir));
+            info!("Static files served from: {}", full_path.display());
+            if static_config.override_dir.is_some() {
+                warn!("static.override_dir is ignored: built without the embed-static feature");
+            }
+            CachedLoader::new(base_dir)
+        };
+        let loader = loader
+            .revalidating(static_config.dev_mode)
+            .with_symlinks(static_config.symlinks)
+            .with_budget(static_config.cache_max_bytes, static_config.cache_max_file_bytes);
+        loader.publish_stats();
+        if static_config.dev_mode {
+            info!("Static dev mode: changed files are reloaded on request");
+        } else if static_config.preload {
+            loader.preload();
+        }
+
+        Self { listeners, loader: Arc::new(loader) }
+    }
 
-        println!("Static files served from: {}", full_path.display());
+    /// Bind every listener and accept connections on all of them until
+    /// shutdown is requested. Connections already accepted keep running;
+    /// see `core_shutdown::wait_for_drain`.
+    pub async fn run(&self) {
+        let tls = &get_config().tls;
+        let wants_tls = self
+            .listeners
+            .iter()
+            .any(|l| matches!(l, ListenerConfig::Tcp { tls: true, .. }));
+        let acceptor = wants_tls.then(|| {
+            Arc::new(TlsAcceptor::new(tls).unwrap_or_else(|e| panic!("Failed to set up TLS: {}", e)))
+        });
 
-        let loader = Arc::new(CachedLoader::new(base_dir));
-        Self {
-            address: address.to_string(),
-            loader,
+        let mut listeners = Vec::new();
+        for config in &self.listeners {
+            let listener = Listener::bind(config, acceptor.as_ref()).await;
+            listeners.push(listener.unwrap_or_else(|e| panic!("{}", e)));
+        }
+        if listeners.is_empty() {
+            panic!("No listeners configured");
+        }
+        for listener in &listeners {
+            info!("Charmline running at {}", listener.url());
         }
-    }
 
-    pub fn run(&self) {
-        let listener = TcpListener::bind(&self.address).expect("Failed to bind port");
-        println!("Charmline running at http://{}/", self.address);
+        let redirect_port = if tls.redirect_http { self.https_port() } else { None };
+        let server_config = &get_config().server;
+        let pool = Arc::new(ConnectionPool::new(
+            server_config.max_connections,
+            server_config.accept_queue,
+            Duration::from_secs(server_config.accept_queue_timeout_secs),
+        ));
 
-        for stream in listener.incoming() {
-            if let Ok(stream) = stream {
-                let loader = Arc::clone(&self.loader);
-                thread::spawn(move || handle_client(stream, loader));
-            }
+        let mut accept_loops = JoinSet::new();
+        for listener in listeners {
+            let pool = Arc::clone(&pool);
+            let loader = Arc::clone(&self.loader);
+            accept_loops.spawn(accept_loop(listener, pool, loader, redirect_port));
         }
+        while accept_loops.join_next().await.is_some() {}
+    }
+
+    /// Port of the first HTTPS listener, the target for HTTP redirects.
+    fn https_port(&self) -> Option<u16> {
+        self.listeners.iter().find_map(|l| match l {
+            ListenerConfig::Tcp { address, tls: true } => {
+                address.rsplit_once(':').and_then(|(_, port)| port.parse().ok())
+            }
+            _ => None,
+        })
     }
 }
 
 // ----- Lifecycle ----- //
 
-fn handle_client(mut stream: TcpStream, loader: Arc<CachedLoader>) {
-    let mut buffer = [0; 8192];
-    let bytes_read = match stream.read(&mut buffer) {
-        Ok(size) => size,
+/// Accept until shutdown starts, giving every connection its own task. The
+/// listener (and a Unix socket file) is released when the loop returns.
+async fn accept_loop(
+    listener: Listener,
+    pool: Arc<ConnectionPool>,
+    loader: Arc<CachedLoader>,
+    redirect_port: Option<u16>,
+) {
+    let retry_after_secs = get_config().server.busy_retry_after_secs;
+
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown_requested() => return,
+        };
+        let accepted = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("Accept failed on {}: {}", listener.url(), e);
+                time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                continue;
+            }
+        };
+
+        let slot = match accepted.peer.map(|ip| (ip, acquire_ip_slot(ip))) {
+            Some((ip, None)) => {
+                warn!(%ip, "Connection limit reached, returning 429");
+                count_rejection(Rejection::PerIpLimit);
+                let response = response_status(StatusCode::TooManyRequests, "Too many connections")
+                    .with_header("Retry-After", retry_after_secs.to_string());
+                tokio::spawn(reject_connection(accepted, response));
+                continue;
+            }
+            Some((_, slot)) => slot,
+            None => None,
+        };
+
+        let Some(queued) = pool.try_enqueue() else {
+            warn!("Connection pool and accept queue full, returning 503");
+            count_rejection(Rejection::Busy);
+            tokio::spawn(reject_connection(accepted, response_service_unavailable(retry_after_secs)));
+            continue;
+        };
+
+        let loader = Arc::clone(&loader);
+        tokio::spawn(async move {
+            let Some(permit) = queued.wait().await else {
+                warn!("No connection slot freed up in time, returning 503");
+                count_rejection(Rejection::Busy);
+                reject_connection(accepted, response_service_unavailable(retry_after_secs)).await;
+                return;
+            };
+            let connection = Connection { accepted, slot, permit };
+            handle_client(connection, loader, redirect_port).await;
+        });
+    }
+}
+
+/// Answer a connection without reading the request. The whole exchange,
+/// including a TLS handshake, gets one short timeout.
+async fn reject_connection(accepted: Accepted, response: HttpResponse) {
+    let _ = time::timeout(REJECT_TIMEOUT, async {
+        let mut stream = accepted.establish().await?;
+        write_timed(&mut stream, &response.to_bytes(false), REJECT_TIMEOUT).await?;
+        stream.close().await;
+        io::Result::Ok(())
+    })
+    .await;
+}
+
+async fn handle_client(connection: Connection, loader: Arc<CachedLoader>, https_port: Option<u16>) {
+    // The permit and IP slot are released when this task ends
+    let Connection { accepted, slot: _slot, permit: _permit } = connection;
+    let config = &get_config().server;
+
+    let handshake_timeout = Duration::from_secs(config.read_timeout_secs);
+    let stream = match time::timeout(handshake_timeout, accepted.establish()).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            debug!("Handshake failed: {}", e);
+            return;
+        }
         Err(_) => return,
     };
+    let redirect_port = https_port.filter(|_| stream.is_plain_tcp());
+
+    let mut reader = BufReader::new(stream);
+    match serve_requests(&mut reader, &loader, redirect_port).await {
+        // The socket keeps the pool permit and IP slot for the whole chat
+        Some(Upgrade { request, handler, span }) => {
+            let _active = track_active();
+            let socket = WebSocket::new(reader);
+            handler(Arc::new(request), socket).instrument(span).await;
+        }
+        None => reader.into_inner().close().await,
+    }
+}
+
+/// Serve requests on one connection until the client closes it, asks for
+/// `Connection: close`, goes idle past the keep-alive timeout or reaches the
+/// per-connection request cap. Pipelined requests are answered in order since
+/// the reader keeps whatever was received beyond the current request.
+/// Each request runs in its own span, keyed by the `X-Request-Id` it is
+/// answered with. Returns the upgrade after a WebSocket handshake.
+async fn serve_requests(
+    reader: &mut BufReader<ClientStream>,
+    loader: &Arc<CachedLoader>,
+    redirect_port: Option<u16>,
+) -> Option<Upgrade> {
+    let config = &get_config().server;
+    let max_requests = config.keep_alive_max_requests.max(1);
 
-    let request = String::from_utf8_lossy(&buffer[..bytes_read]);
+    let idle_timeout = Duration::from_secs(config.keep_alive_timeout_secs);
+    let write_timeout = Duration::from_secs(config.write_timeout_secs);
+
+    for served in 1..=max_requests {
+        if !wait_for_request(reader, idle_timeout).await {
+            return None;
+        }
+        // From the first byte on, each read gets the shorter read timeout and
+        // the whole request has to arrive before the deadline
+        let limits = RequestLimits::new(config);
 
-    // Parse the request line
-    let mut lines = request.lines();
-    let first_line = lines.next().unwrap_or("");
-    let mut parts = first_line.split_whitespace();
-    let method = parts.next().unwrap_or("");
-    let path = parts.next().unwrap_or("/");
+        // Held until the response, including a streamed body, is written
+        let mut _active = None;
 
-    // Extract body (after a blank line)
-    let body_start = request.find("\r\n\r\n").map(|i| i + 4);
-    let body = body_start
-        .map(|i| request[i..].to_string())
-        .unwrap_or_default();
+        let (mut response, keep_alive, head_only) = match read_request(reader, &limits).await {
+            Ok(mut request) => {
+                _active = Some(track_active());
+                let keep_alive =
+                    request.wants_keep_alive() && served < max_requests && !is_shutting_down();
+                let head_only = request.method == "HEAD";
+                let started = Instant::now();
+                let request_id = request_id_for(&request);
+                let span = request_span(&request, &request_id);
 
-    let response = match method {
-        "GET" | "POST" => handle_route(path, &loader, &body),
-        _ => response_method_not_allowed(),
+                let mut response = match redirect_port {
+                    Some(port) => https_redirect(&request, port),
+                    None if is_upgrade_request(&request) => match span.in_scope(|| handshake(&mut request)) {
+                        Ok((handler, response)) => {
+                            if let Some(pattern) = request.route {
+                                span.record("route", pattern);
+                            }
+                            let response = response.with_header(REQUEST_ID_HEADER, request_id);
+                            let written =
+                                write_timed(reader.get_mut(), &response.to_bytes(true), write_timeout).await;
+                            span.in_scope(|| info!("WebSocket opened"));
+                            return written.is_ok().then_some(Upgrade { request, handler, span });
+                        }
+                        Err(response) => response,
+                    },
+                    None => handle_route(request, loader).instrument(span.clone()).await,
+                };
+                response.set_header(REQUEST_ID_HEADER, request_id);
+                span.in_scope(|| {
+                    info!(
+                        status = response.status.code(),
+                        elapsed_ms = started.elapsed().as_millis() as u64,
+                        "Request handled"
+                    )
+                });
+                (response, keep_alive, head_only)
+            }
+            Err(RequestError::ConnectionClosed) => return None,
+            Err(RequestError::Io(e)) => {
+                debug!("Read failed: {}", e);
+                return None;
+            }
+            Err(e) => {
+                // Framing is unknown after a bad request, so the connection is closed
+                warn!("Rejected request: {}", e);
+                let (status, rejection) = match e {
+                    RequestError::HeadersTooLarge => {
+                        (StatusCode::RequestHeaderFieldsTooLarge, Rejection::HeadersTooLarge)
+                    }
+                    RequestError::BodyTooLarge => (StatusCode::PayloadTooLarge, Rejection::BodyTooLarge),
+                    RequestError::UriTooLong => (StatusCode::UriTooLong, Rejection::UriTooLong),
+                    RequestError::UnsupportedEncoding => {
+                        (StatusCode::NotImplemented, Rejection::UnsupportedEncoding)
+                    }
+                    RequestError::TimedOut => (StatusCode::RequestTimeout, Rejection::Timeout),
+                    _ => (StatusCode::BadRequest, Rejection::BadRequest),
+                };
+                count_rejection(rejection);
+                (response_status(status, &e.to_string()), false, false)
+            }
+        };
+
+        let stream = reader.get_mut();
+        let bytes = if head_only {
+            response.head_bytes(keep_alive)
+        } else {
+            response.to_bytes(keep_alive)
+        };
+        if write_timed(stream, &bytes, write_timeout).await.is_err() {
+            return None;
+        }
+        if let Some(mut body) = response.stream.take().filter(|_| !head_only) {
+            let mut chunked = ChunkedWriter::new(stream, write_timeout);
+            while let Some(piece) = body.recv().await {
+                if chunked.write_chunk(&piece).await.is_err() {
+                    return None;
+                }
+            }
+            if chunked.finish().await.is_err() {
+                return None;
+            }
+        }
+        if !keep_alive {
+            return None;
+        }
+    }
+    None
+}
+
+// ----- Helpers ----- //
+
+/// Wait up to the keep-alive timeout for the next request to start, without
+/// consuming anything. Pipelined bytes already buffered count as started.
+/// Idle connections are dropped as soon as shutdown starts.
+async fn wait_for_request(reader: &mut BufReader<ClientStream>, idle_timeout: Duration) -> bool {
+    if !reader.buffer().is_empty() {
+        return true;
+    }
+
+    // Idle keep-alive connections end here once the timeout fires
+    tokio::select! {
+        filled = time::timeout(idle_timeout, reader.fill_buf()) => match filled {
+            Ok(Ok(buf)) => !buf.is_empty(),
+            Ok(Err(e)) => {
+                debug!("Read failed: {}", e);
+                false
+            }
+            Err(_) => false,
+        },
+        _ = shutdown_requested() => false,
+    }
+}
+
+/// 301 to the same host and path on the HTTPS port. A `Host` header that is
+/// not a plain host[:port] gets a 400, so a client cannot choose where the
+/// redirect points.
+fn https_redirect(request: &HttpRequest, https_port: u16) -> HttpResponse {
+    let host = request.header("Host").unwrap_or("localhost");
+    let Some(hostname) = redirect_hostname(host) else {
+        warn!("Refused HTTPS redirect for Host {:?}", host);
+        return response_status(StatusCode::BadRequest, "Invalid Host header");
     };
 
-    let _ = stream.write_all(&response.to_bytes());
+    let mut location = match https_port {
+        443 => format!("https://{}{}", hostname, request.path),
+        port => format!("https://{}:{}{}", hostname, port, request.path),
+    };
+    if !request.query.is_empty() {
+        location.push('?');
+        location.push_str(&request.query);
+    }
+
+    response_status(StatusCode::MovedPermanently, "Moved to HTTPS").with_header("Location", location)
+}
+
+// The host part of a `Host` header, without its port: a DNS name, IPv4
+// address or bracketed IPv6 literal, and at most a numeric port
+fn redirect_hostname(host: &str) -> Option<&str> {
+    let (hostname, port) = match host.strip_prefix('[') {
+        Some(rest) => {
+            let (literal, after) = rest.split_once(']')?;
+            let valid = !literal.is_empty() && literal.chars().all(|c| c.is_ascii_hexdigit() || c == ':' || c == '.');
+            if !valid {
+                return None;
+            }
+            (&host[..literal.len() + 2], after)
+        }
+        None => {
+            let end = host.find(':').unwrap_or(host.len());
+            let name = &host[..end];
+            let valid = !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+                && !name.starts_with(['.', '-']);
+            if !valid {
+                return None;
+            }
+            (name, &host[end..])
+        }
+    };
+
+    match port.strip_prefix(':') {
+        None if port.is_empty() => Some(hostname),
+        Some(port) if port.parse::<u16>().is_ok() && port.bytes().all(|b| b.is_ascii_digit()) => Some(hostname),
+        _ => None,
+    }
+}
+
+// ----- Tests ----- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirects_only_to_plain_hosts() {
+        let valid = [
+            ("example.com", "example.com"),
+            ("example.com:8080", "example.com"),
+            ("127.0.0.1:80", "127.0.0.1"),
+            ("[::1]", "[::1]"),
+            ("[::1]:8080", "[::1]"),
+            ("[::ffff:127.0.0.1]:80", "[::ffff:127.0.0.1]"),
+        ];
+        for (host, hostname) in valid {
+            assert_eq!(redirect_hostname(host), Some(hostname), "{}", host);
+        }
+
+        let invalid = [
+            "",
+            "evil.com/path",
+            "user@evil.com",
+            "example.com evil.com",
+            "example.com\r\nSet-Cookie: x=1",
+            "example.com\t",
+            "example.com:",
+            "example.com:+80",
+            "example.com:99999",
+            "example.com:80:80",
+            "example.com:80/x",
+            "[::1",
+            "[]:80",
+            "[::1]x",
+            "[::1]:80@evil.com",
+            ".evil.com",
+            "evil.com?x",
+            "evil.com#x",
+        ];
+        for host in invalid {
+            assert_eq!(redirect_hostname(host), None, "{:?}", host);
+        }
+    }
 }
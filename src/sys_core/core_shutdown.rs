@@ -0,0 +1,111 @@
+// ----- Imports ----- //
+
+use std::{
+    future::Future,
+    sync::{
+        Condvar, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+use tracing::{Instrument, info, warn};
+
+// ----- Global Shutdown State ----- //
+
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static STOP_SIGNAL: Notify = Notify::const_new(); // Wakes accept loops and idle connections
+static ACTIVE: Mutex<usize> = Mutex::new(0); // In-flight requests and background jobs
+static DRAINED: Condvar = Condvar::new();
+
+/// SIGINT/SIGTERM (Ctrl+C / service stop on Windows) start a graceful
+/// shutdown. A second signal exits immediately.
+pub fn install_signal_handler() {
+    ctrlc::set_handler(|| {
+        if STOPPING.swap(true, Ordering::SeqCst) {
+            warn!("Second signal received, exiting now");
+            std::process::exit(1);
+        }
+        info!("Signal received, no longer accepting connections");
+        STOP_SIGNAL.notify_waiters();
+    })
+    .expect("Failed to install signal handler");
+}
+
+pub fn is_shutting_down() -> bool {
+    STOPPING.load(Ordering::SeqCst)
+}
+
+/// Resolves once shutdown has started, for racing against accepts and reads.
+pub async fn shutdown_requested() {
+    let notified = STOP_SIGNAL.notified();
+    tokio::pin!(notified);
+    // Register before checking the flag so a signal in between is not missed
+    notified.as_mut().enable();
+    if is_shutting_down() {
+        return;
+    }
+    notified.await;
+}
+
+// ----- Tracking ----- //
+
+/// Keeps shutdown waiting while alive. Held for the duration of a request or
+/// a background job.
+pub struct ActiveGuard(());
+
+pub fn track_active() -> ActiveGuard {
+    *ACTIVE.lock().unwrap() += 1;
+    ActiveGuard(())
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        let mut active = ACTIVE.lock().unwrap();
+        *active -= 1;
+        if *active == 0 {
+            DRAINED.notify_all();
+        }
+    }
+}
+
+/// Spawn a background task that shutdown waits for, e.g. summary and DB jobs.
+/// The task stays in the caller's span, so its logs keep the request and
+/// session IDs.
+pub fn spawn_tracked<F>(job: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let guard = track_active();
+    tokio::spawn(async move {
+        let _guard = guard;
+        job.await;
+    }.in_current_span());
+}
+
+// ----- Draining ----- //
+
+/// Block until every tracked request and job has finished, or until
+/// `deadline` passes. Returns whether everything drained in time. Call it
+/// from a blocking thread; the tasks it waits for need the runtime.
+pub fn wait_for_drain(deadline: Duration) -> bool {
+    let started = Instant::now();
+    let mut active = ACTIVE.lock().unwrap();
+
+    loop {
+        if *active == 0 {
+            info!("All requests and jobs finished");
+            return true;
+        }
+
+        let Some(remaining) = deadline.checked_sub(started.elapsed()) else {
+            warn!("Deadline reached with {} active, exiting anyway", *active);
+            return false;
+        };
+
+        info!("Waiting on {} active...", *active);
+        let wait = remaining.min(Duration::from_secs(1));
+        active = DRAINED.wait_timeout(active, wait).unwrap().0;
+    }
+}
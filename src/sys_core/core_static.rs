@@ -0,0 +1,393 @@
+// ----- Imports ----- //
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, atomic::Ordering};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use tracing::{debug, error, warn};
+
+use crate::sys_core::core_encoding::{Encoding, is_compressible, negotiate_encoding};
+use crate::sys_core::core_mime::{SNIFF_BYTES, content_type_for, sniff_content_type};
+use crate::sys_core::core_paths::{PathRejection, normalize_static_path, resolve_static_path};
+use crate::sys_core::core_responses::{response_not_found, response_ok, response_status};
+use crate::sys_core::core_template::is_template;
+use crate::sys_core::{HttpRequest, HttpResponse, StatusCode, get_config};
+use crate::sys_resource::{CachedFile, CachedLoader, DiskFile, LoadedFile, get_cache_stats, rendered_key};
+
+// ----- Constants ----- //
+
+const STREAM_CHUNK_BYTES: usize = 64 * 1024; // Read size for files served from disk
+
+// ----- Static Files ----- //
+
+/// Lookup order: the file itself, the directory's index file, the
+/// `clean_urls` rewrites, then the SPA fallback. Anything else is a 404.
+pub fn serve_static(request: &HttpRequest, loader: &Arc<CachedLoader>) -> HttpResponse {
+    let path = match resolve_static_path(&request.path) {
+        Ok(path) => path,
+        Err(PathRejection::Malformed) => {
+            warn!("Rejected malformed path → {}", request.path);
+            return error_response(loader, StatusCode::BadRequest, "Invalid path");
+        }
+        // Hidden files get the same answer as missing ones
+        Err(rejection) => {
+            warn!("Rejected path ({:?}) → {}", rejection, request.path);
+            return error_response(loader, StatusCode::NotFound, "File not found");
+        }
+    };
+    let path = path.as_str();
+    let static_config = &get_config().static_files;
+
+    // Try direct file first
+    if !path.is_empty()
+        && let Some(file) = loader.load(path)
+    {
+        return static_file_response(request, loader, path, file);
+    }
+
+    // Directories answer with their index file, from a URL ending in `/` so
+    // the page's relative links resolve inside the directory
+    let index_path = join_path(path, &static_config.index_file);
+    if let Some(file) = loader.load(&index_path) {
+        if !request.path.ends_with('/') {
+            return redirect_to_directory(request);
+        }
+        return static_file_response(request, loader, &index_path, file);
+    }
+
+    let extensionless = !path.rsplit('/').next().unwrap_or("").contains('.');
+    if extensionless && !path.is_empty() {
+        for template in &static_config.clean_urls {
+            let target = template.replace("{path}", path);
+            if let Some((name, file)) = load_configured(loader, &target) {
+                return static_file_response(request, loader, &name, file);
+            }
+        }
+    }
+
+    if extensionless
+        && accepts_html(request)
+        && let Some(fallback) = &static_config.spa_fallback
+        && let Some((name, file)) = load_configured(loader, fallback)
+    {
+        return static_file_response(request, loader, &name, file);
+    }
+
+    debug!("File not found at: {}", path);
+    error_response(loader, StatusCode::NotFound, "File not found")
+}
+
+/// The `static.error_pages` page for `status`, or `message` as plain text.
+/// Error pages are never cached by clients, so a fixed file shows at once.
+fn error_response(loader: &CachedLoader, status: StatusCode, message: &str) -> HttpResponse {
+    let page = get_config()
+        .static_files
+        .error_pages
+        .get(&status.code())
+        .and_then(|page| load_configured(loader, page));
+
+    let page = page.and_then(|(name, file)| match file {
+        LoadedFile::Cached(file) if is_template(&name) => match loader.load_rendered(&name, &file) {
+            Ok(page) => Some((name, LoadedFile::Cached(page))),
+            Err(e) => {
+                error!("Template error in {}", e);
+                None
+            }
+        },
+        file => Some((name, file)),
+    });
+
+    match page {
+        Some((name, LoadedFile::Cached(file))) => {
+            let content_type = content_type_for(&name).unwrap_or_else(|| sniff_content_type(&file.bytes));
+            HttpResponse::new(status)
+                .with_body(&content_type, file.bytes)
+                .with_header("Cache-Control", "no-cache")
+                .with_header("X-Content-Type-Options", "nosniff")
+        }
+        Some((name, LoadedFile::Disk(_))) => {
+            warn!("Error page {} is over the cache's file size limit", name);
+            response_status(status, message)
+        }
+        None => response_status(status, message),
+    }
+}
+
+/// 301 from `/dir` to `/dir/`, keeping the query string.
+fn redirect_to_directory(request: &HttpRequest) -> HttpResponse {
+    let mut location = format!("{}/", request.path);
+    if !request.query.is_empty() {
+        location.push('?');
+        location.push_str(&request.query);
+    }
+    response_status(StatusCode::MovedPermanently, "Moved").with_header("Location", location)
+}
+
+/// Response for a loaded file with validators and caching policy attached.
+/// A matching `If-None-Match` or `If-Modified-Since` turns it into a
+/// bodiless 304.
+fn static_file_response(
+    request: &HttpRequest,
+    loader: &CachedLoader,
+    filename: &str,
+    file: LoadedFile,
+) -> HttpResponse {
+    // The extension decides; content is only sniffed when it is unknown
+    let content_type = content_type_for(filename).unwrap_or_else(|| match &file {
+        LoadedFile::Cached(file) => sniff_content_type(&file.bytes),
+        LoadedFile::Disk(file) => sniff_content_type(&read_head(&file.path)),
+    });
+
+    let mut response = match file {
+        LoadedFile::Cached(file) if is_template(filename) => match loader.load_rendered(filename, &file) {
+            Ok(page) => cached_file_response(request, loader, &rendered_key(filename), &content_type, page),
+            Err(e) => {
+                error!("Template error in {}", e);
+                response_status(StatusCode::InternalServerError, "Template error")
+            }
+        },
+        LoadedFile::Cached(file) => cached_file_response(request, loader, filename, &content_type, file),
+        LoadedFile::Disk(file) => disk_file_response(request, &content_type, file),
+    };
+    // Browsers must not second-guess the type, e.g. run a text file as script
+    response.set_header("X-Content-Type-Options", "nosniff");
+
+    // In development browsers revalidate every time, so edits show on reload
+    let static_config = &get_config().static_files;
+    let cache_control = if static_config.dev_mode {
+        "no-cache"
+    } else {
+        static_config.cache_control_for(&request.path)
+    };
+    response.set_header("Cache-Control", cache_control);
+    response
+}
+
+/// Compressible files are served from the loader's encoded variants, so each
+/// file is compressed once per encoding. `cache_key` is the file's name, or
+/// the `rendered_key` of a rendered template.
+fn cached_file_response(
+    request: &HttpRequest,
+    loader: &CachedLoader,
+    cache_key: &str,
+    content_type: &str,
+    file: CachedFile,
+) -> HttpResponse {
+    let compressible = is_compressible(content_type);
+
+    let encoded = negotiate_encoding(request)
+        .filter(|_| compressible)
+        .map(|encoding| (encoding, loader.load_encoded(cache_key, &file, encoding)))
+        .filter(|(_, bytes)| bytes.len() < file.bytes.len());
+
+    // Each representation needs its own strong validator
+    let etag = match &encoded {
+        Some((encoding, _)) => variant_etag(&file.etag, *encoding),
+        None => file.etag.clone(),
+    };
+
+    let mut response = if is_not_modified(request, &etag, file.modified) {
+        HttpResponse::new(StatusCode::NotModified)
+    } else {
+        match encoded {
+            Some((encoding, bytes)) => response_ok(content_type, bytes)
+                .with_header("Content-Encoding", encoding.header_value()),
+            None => response_ok(content_type, file.bytes),
+        }
+    };
+
+    response.set_header("ETag", etag);
+    if let Some(modified) = file.modified {
+        response.set_header("Last-Modified", http_date(modified));
+    }
+    if compressible {
+        response.add_vary("Accept-Encoding");
+    }
+    response
+}
+
+/// Large files go out uncompressed in chunks read on a blocking thread, so
+/// only a few chunks are in memory at a time.
+fn disk_file_response(request: &HttpRequest, content_type: &str, file: DiskFile) -> HttpResponse {
+    let mut response = if is_not_modified(request, &file.etag, file.modified) {
+        HttpResponse::new(StatusCode::NotModified)
+    } else {
+        // Opened up front so a file that just vanished is still a 404
+        let Ok(mut reader) = File::open(&file.path) else {
+            return response_not_found("File not found");
+        };
+        let (response, body) = HttpResponse::streaming(content_type);
+
+        tokio::task::spawn_blocking(move || {
+            let mut buffer = vec![0; STREAM_CHUNK_BYTES];
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) => break,
+                    // Fails once the client is gone
+                    Ok(n) => {
+                        if body.blocking_send(buffer[..n].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Read failed for {}: {}", file.path.display(), e);
+                        break;
+                    }
+                }
+            }
+        });
+        response
+    };
+
+    response.set_header("ETag", file.etag);
+    if let Some(modified) = file.modified {
+        response.set_header("Last-Modified", http_date(modified));
+    }
+    response
+}
+
+/// GET /api/server/cache
+/// Returns the static file cache's occupancy and hit/miss/eviction counters.
+pub async fn handle_server_cache(_request: Arc<HttpRequest>) -> HttpResponse {
+    let json = match get_cache_stats() {
+        Some(stats) => json!({
+            "max_bytes": stats.max_bytes,
+            "max_file_bytes": stats.max_file_bytes,
+            "bytes": stats.bytes.load(Ordering::Relaxed),
+            "entries": stats.entries.load(Ordering::Relaxed),
+            "hits": stats.hits.load(Ordering::Relaxed),
+            "misses": stats.misses.load(Ordering::Relaxed),
+            "evictions": stats.evictions.load(Ordering::Relaxed),
+            "streamed": stats.streamed.load(Ordering::Relaxed),
+        }),
+        None => json!({ "error": "Static file cache not running" }),
+    };
+
+    response_ok(
+        "application/json; charset=utf-8",
+        json.to_string().into_bytes(),
+    )
+}
+
+// ----- Conditional Requests ----- //
+
+/// `If-None-Match` takes precedence; `If-Modified-Since` is only consulted
+/// when the client sent no entity tags (RFC 9110 §13.2.2).
+fn is_not_modified(request: &HttpRequest, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = request.header("If-None-Match") {
+        return if_none_match
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag);
+    }
+
+    match (request.header("If-Modified-Since"), modified) {
+        (Some(since), Some(modified)) => DateTime::parse_from_rfc2822(since)
+            .map(|since| DateTime::<Utc>::from(modified).timestamp() <= since.timestamp())
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// `"hash"` → `"hash-br"` for the compressed representation.
+fn variant_etag(etag: &str, encoding: Encoding) -> String {
+    format!(
+        "{}-{}\"",
+        etag.trim_end_matches('"'),
+        encoding.header_value()
+    )
+}
+
+/// IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+// ----- Helpers ----- //
+
+// Load a file named in the config, or built from an already resolved path.
+// Neither is a URL, so a `%` in it is part of the name.
+fn load_configured(loader: &CachedLoader, name: &str) -> Option<(String, LoadedFile)> {
+    let name = normalize_static_path(name).ok()?;
+    let file = loader.load(&name)?;
+    Some((name, file))
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+// Browsers navigating to a page send `text/html`; `fetch` calls and asset
+// requests get a real 404 rather than the app shell
+fn accepts_html(request: &HttpRequest) -> bool {
+    request
+        .header("Accept")
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+// The first bytes of a streamed file, for sniffing; empty if it cannot be read
+fn read_head(path: &Path) -> Vec<u8> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    if let Ok(file) = File::open(path) {
+        let _ = file.take(SNIFF_BYTES as u64).read_to_end(&mut head);
+    }
+    head
+}
+
+// ----- Tests ----- //
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::sys_core::core_config::init_test_config;
+
+    fn get(loader: &Arc<CachedLoader>, path: &str) -> HttpResponse {
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            query: String::new(),
+            version: "HTTP/1.1".to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            params: Default::default(),
+            route: None,
+        };
+        serve_static(&request, loader)
+    }
+
+    #[test]
+    fn serves_names_containing_percent_signs() {
+        init_test_config();
+        let root = env::temp_dir().join(format!("charmline-static-percent-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("pages")).unwrap();
+        fs::write(root.join("A.txt"), "decoded twice").unwrap();
+        fs::write(root.join("%41.txt"), "literal").unwrap();
+        fs::write(root.join("100%.txt"), "percent").unwrap();
+        fs::write(root.join("pages/50%off.html"), "clean").unwrap();
+        let loader = Arc::new(CachedLoader::new(&root));
+
+        let response = get(&loader, "/%2541.txt");
+        assert_eq!((response.status, response.body.as_slice()), (StatusCode::Ok, &b"literal"[..]));
+        let response = get(&loader, "/100%25.txt");
+        assert_eq!((response.status, response.body.as_slice()), (StatusCode::Ok, &b"percent"[..]));
+        // Through a `clean_urls` rewrite, `pages/{path}.html` by default
+        let response = get(&loader, "/50%25off");
+        assert_eq!((response.status, response.body.as_slice()), (StatusCode::Ok, &b"clean"[..]));
+
+        assert_eq!(get(&loader, "/100%.txt").status, StatusCode::BadRequest);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
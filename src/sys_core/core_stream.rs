@@ -0,0 +1,137 @@
+// ----- Imports ----- //
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+    time,
+};
+use tokio_rustls::server::TlsStream;
+
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+// ----- Constants ----- //
+
+const CLOSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// ----- Structs ----- //
+
+/// A client connection as seen by the request loop: plain TCP, TLS or a
+/// Unix domain socket.
+pub enum ClientStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+// ----- Implementations ----- //
+
+impl ClientStream {
+    /// Unencrypted TCP, i.e. a connection an HTTPS redirect applies to.
+    /// Unix sockets are left alone since a local proxy terminates TLS.
+    pub fn is_plain_tcp(&self) -> bool {
+        matches!(self, ClientStream::Plain(_))
+    }
+
+    /// Politely end the connection. TLS sends `close_notify` so the client
+    /// can tell a finished response from a truncated one.
+    pub async fn close(mut self) {
+        let _ = time::timeout(CLOSE_TIMEOUT, self.shutdown()).await;
+    }
+}
+
+impl AsyncRead for ClientStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ClientStream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            ClientStream::Tls(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(unix)]
+            ClientStream::Unix(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ClientStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ClientStream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            ClientStream::Tls(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(unix)]
+            ClientStream::Unix(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ClientStream::Plain(s) => Pin::new(s).poll_flush(cx),
+            ClientStream::Tls(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(unix)]
+            ClientStream::Unix(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ClientStream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            ClientStream::Tls(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(unix)]
+            ClientStream::Unix(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
+
+// ----- Writing ----- //
+
+/// Write and flush `bytes`, giving up after `timeout`. An error means the
+/// client is gone or stopped reading.
+pub async fn write_timed<W>(stream: &mut W, bytes: &[u8], timeout: Duration) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let write = async {
+        stream.write_all(bytes).await?;
+        stream.flush().await
+    };
+    time::timeout(timeout, write)
+        .await
+        .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+}
+
+// ----- Chunked Bodies ----- //
+
+/// Frames every piece as one HTTP/1.1 chunk and flushes it, so streamed
+/// responses reach the client as they are produced.
+pub struct ChunkedWriter<'a, W: AsyncWrite + Unpin> {
+    inner: &'a mut W,
+    timeout: Duration,
+}
+
+impl<'a, W: AsyncWrite + Unpin> ChunkedWriter<'a, W> {
+    pub fn new(inner: &'a mut W, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+
+    pub async fn write_chunk(&mut self, buf: &[u8]) -> io::Result<()> {
+        // An empty chunk would end the body
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let mut chunk = format!("{:X}\r\n", buf.len()).into_bytes();
+        chunk.extend_from_slice(buf);
+        chunk.extend_from_slice(b"\r\n");
+        write_timed(self.inner, &chunk, self.timeout).await
+    }
+
+    /// Write the terminating zero-length chunk.
+    pub async fn finish(self) -> io::Result<()> {
+        write_timed(self.inner, b"0\r\n\r\n", self.timeout).await
+    }
+}
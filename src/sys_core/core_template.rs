@@ -0,0 +1,391 @@
+// ----- Imports ----- //
+
+use std::sync::LazyLock;
+
+use chrono::{Datelike, Utc};
+use serde_json::{Value, json};
+
+use crate::sys_core::{core_paths::normalize_static_path, get_config};
+use crate::sys_resource::{CachedLoader, LoadedFile};
+
+// ----- Constants ----- //
+
+const MAX_INCLUDE_DEPTH: usize = 8; // Also stops a page that includes itself
+
+// ----- Context ----- //
+
+/// What placeholders can name: `brand.*` from the `"branding"` section and
+/// `server.*` facts about this instance. Fixed for the life of the process.
+static CONTEXT: LazyLock<Value> = LazyLock::new(|| {
+    let config = get_config();
+    json!({
+        "brand": config.branding,
+        "server": {
+            "version": env!("CARGO_PKG_VERSION"),
+            "dev_mode": config.static_files.dev_mode,
+            "year": Utc::now().year(),
+        },
+    })
+});
+
+// ----- Structs ----- //
+
+/// A parsed page, cached by `CachedLoader` next to the file it came from.
+///
+/// - `{{ brand.company_name }}` inserts a value, HTML-escaped
+/// - `{{> partials/head.html }}` inserts another template, by its path
+///   under the static root
+/// - `{{#if brand.logo_url }} ... {{else}} ... {{/if}}` keeps one branch,
+///   the first when the value is set and not `false`, `0` or empty
+pub struct Template {
+    nodes: Vec<Node>,
+    source_len: usize,
+}
+
+enum Node {
+    Text(String),
+    Value(String),
+    Include(String),
+    If {
+        key: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// An `{{#if}}` still being parsed, or the top level of the template.
+#[derive(Default)]
+struct Block {
+    key: Option<String>,
+    then: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+// ----- Implementations ----- //
+
+impl Template {
+    pub fn compile(source: &str) -> Result<Self, String> {
+        let mut blocks = vec![Block::default()];
+        let mut rest = source;
+
+        while let Some(start) = rest.find("{{") {
+            let offset = source.len() - rest.len() + start;
+            let line = source.as_bytes()[..offset].iter().filter(|&&b| b == b'\n').count() + 1;
+            let error = |message: &str| format!("line {}: {}", line, message);
+
+            push_text(&mut blocks, &rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or_else(|| error("unclosed {{"))?;
+            let tag = after[..end].trim();
+            rest = &after[end + 2..];
+
+            if let Some(path) = tag.strip_prefix('>') {
+                current(&mut blocks).push(Node::Include(path.trim().to_string()));
+            } else if let Some(key) = tag.strip_prefix("#if ").map(str::trim).filter(|k| is_key(k)) {
+                blocks.push(Block {
+                    key: Some(key.to_string()),
+                    ..Block::default()
+                });
+            } else if tag == "else" {
+                let block = blocks.last_mut().unwrap();
+                if block.key.is_none() || block.otherwise.is_some() {
+                    return Err(error("{{else}} outside {{#if}}"));
+                }
+                block.otherwise = Some(Vec::new());
+            } else if tag == "/if" {
+                if blocks.len() == 1 {
+                    return Err(error("{{/if}} without {{#if}}"));
+                }
+                let block = blocks.pop().unwrap();
+                current(&mut blocks).push(Node::If {
+                    key: block.key.unwrap_or_default(),
+                    then: block.then,
+                    otherwise: block.otherwise.unwrap_or_default(),
+                });
+            } else if is_key(tag) {
+                current(&mut blocks).push(Node::Value(tag.to_string()));
+            } else {
+                return Err(error(&format!("unknown tag {{{{ {} }}}}", tag)));
+            }
+        }
+        push_text(&mut blocks, rest);
+
+        if blocks.len() > 1 {
+            return Err("unclosed {{#if}}".to_string());
+        }
+        Ok(Self {
+            nodes: blocks.pop().unwrap().then,
+            source_len: source.len(),
+        })
+    }
+
+    /// Approximate memory held, for the cache's byte budget.
+    pub fn size(&self) -> usize {
+        self.source_len
+    }
+
+    /// Fill in the page. Included templates come through `loader`, so they
+    /// are cached and revalidated like any other file; the name and ETag of
+    /// each one used are added to `includes`.
+    pub fn render(&self, loader: &CachedLoader, includes: &mut Vec<(String, String)>) -> Result<Vec<u8>, String> {
+        self.render_in(&CONTEXT, loader, includes)
+    }
+
+    fn render_in(
+        &self,
+        context: &Value,
+        loader: &CachedLoader,
+        includes: &mut Vec<(String, String)>,
+    ) -> Result<Vec<u8>, String> {
+        let mut out = String::with_capacity(self.source_len);
+        render_nodes(&self.nodes, context, loader, 0, includes, &mut out)?;
+        Ok(out.into_bytes())
+    }
+}
+
+// ----- Static Files ----- //
+
+/// Whether `static.template_extensions` marks the file as a template.
+pub fn is_template(filename: &str) -> bool {
+    let extension = filename.rsplit_once('.').map_or("", |(_, ext)| ext);
+    get_config()
+        .static_files
+        .template_extensions
+        .iter()
+        .any(|ext| ext.trim_start_matches('.').eq_ignore_ascii_case(extension))
+}
+
+// ----- Helpers ----- //
+
+fn current(blocks: &mut [Block]) -> &mut Vec<Node> {
+    let block = blocks.last_mut().unwrap();
+    match &mut block.otherwise {
+        Some(otherwise) => otherwise,
+        None => &mut block.then,
+    }
+}
+
+fn push_text(blocks: &mut [Block], text: &str) {
+    if !text.is_empty() {
+        current(blocks).push(Node::Text(text.to_string()));
+    }
+}
+
+fn is_key(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+}
+
+fn render_nodes(
+    nodes: &[Node],
+    context: &Value,
+    loader: &CachedLoader,
+    depth: usize,
+    includes: &mut Vec<(String, String)>,
+    out: &mut String,
+) -> Result<(), String> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Value(key) => escape_html(&display(lookup(context, key)), out),
+            Node::If { key, then, otherwise } => {
+                let branch = if is_truthy(lookup(context, key)) { then } else { otherwise };
+                render_nodes(branch, context, loader, depth, includes, out)?;
+            }
+            Node::Include(path) => {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(format!("includes nested deeper than {} at {}", MAX_INCLUDE_DEPTH, path));
+                }
+                let name = normalize_static_path(path).map_err(|_| format!("invalid include path {}", path))?;
+                let Some(LoadedFile::Cached(file)) = loader.load(&name) else {
+                    return Err(format!("include {} not found", name));
+                };
+                let template = loader.load_template(&name, &file)?;
+                includes.push((name, file.etag));
+                render_nodes(&template.nodes, context, loader, depth + 1, includes, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lookup<'a>(context: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.').try_fold(context, |value, part| value.get(part))
+}
+
+fn display(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn is_truthy(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+        Some(Value::String(s)) => !s.is_empty(),
+        Some(Value::Number(n)) => n.as_f64() != Some(0.0),
+        Some(Value::Array(items)) => !items.is_empty(),
+        Some(_) => true,
+    }
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+// ----- Tests ----- //
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    // A static root holding `files`, named after the test
+    fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("charmline-template-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (filename, content) in files {
+            let path = root.join(filename);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    fn context() -> Value {
+        json!({
+            "brand": {
+                "name": "Wako",
+                "html": "<b class=\"x\">Tom & Jerry's</b>",
+                "empty": "",
+                "count": 3,
+                "zero": 0,
+                "on": true,
+                "off": false,
+            },
+        })
+    }
+
+    fn render(source: &str, loader: &CachedLoader) -> Result<String, String> {
+        let bytes = Template::compile(source)?.render_in(&context(), loader, &mut Vec::new())?;
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+
+    // No static directory, for templates without includes
+    fn render_plain(source: &str) -> Result<String, String> {
+        render(source, &CachedLoader::embedded(None))
+    }
+
+    #[test]
+    fn fills_in_values() {
+        assert_eq!(render_plain("Hi {{ brand.name }}!").unwrap(), "Hi Wako!");
+        assert_eq!(render_plain("{{brand.count}} {{ brand.on }}").unwrap(), "3 true");
+        assert_eq!(render_plain("[{{ brand.missing }}{{ nothing.at.all }}]").unwrap(), "[]");
+        assert_eq!(render_plain("no tags { } }}").unwrap(), "no tags { } }}");
+    }
+
+    #[test]
+    fn escapes_values_for_html() {
+        assert_eq!(
+            render_plain("{{ brand.html }}").unwrap(),
+            "&lt;b class=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/b&gt;"
+        );
+    }
+
+    #[test]
+    fn renders_includes_and_reports_them() {
+        let root = fixture(
+            "includes",
+            &[
+                ("partials/outer.html", "<{{> partials/inner.html }}>"),
+                ("partials/inner.html", "{{ brand.name }}"),
+            ],
+        );
+        let loader = CachedLoader::new(&root);
+
+        let mut includes = Vec::new();
+        let template = Template::compile("[{{> partials/outer.html }}]").unwrap();
+        let bytes = template.render_in(&context(), &loader, &mut includes).unwrap();
+        assert_eq!(bytes, b"[<Wako>]");
+
+        let names: Vec<&str> = includes.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["partials/outer.html", "partials/inner.html"]);
+        let Some(LoadedFile::Cached(inner)) = loader.load("partials/inner.html") else {
+            panic!("inner.html not loaded");
+        };
+        assert_eq!(includes[1].1, inner.etag);
+
+        assert!(render("{{> partials/missing.html }}", &loader).unwrap_err().contains("not found"));
+        assert!(render("{{> ../outside.html }}", &loader).unwrap_err().contains("invalid include path"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn keeps_one_branch_of_nested_ifs() {
+        let source = "{{#if brand.on }}a{{#if brand.empty }}b{{else}}c{{/if}}{{else}}d{{#if brand.name}}e{{/if}}{{/if}}";
+        assert_eq!(render_plain(source).unwrap(), "ac");
+
+        let source = "{{#if brand.off }}a{{else}}{{#if brand.zero }}b{{else}}{{#if brand.count }}c{{/if}}{{/if}}{{/if}}";
+        assert_eq!(render_plain(source).unwrap(), "c");
+
+        assert_eq!(render_plain("{{#if brand.missing }}x{{/if}}y").unwrap(), "y");
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        let cases = [
+            ("a {{else}} b", "line 1: {{else}} outside {{#if}}"),
+            ("{{#if brand.on }}{{else}}{{else}}{{/if}}", "line 1: {{else}} outside {{#if}}"),
+            ("a\nb {{/if}}", "line 2: {{/if}} without {{#if}}"),
+            ("{{#if brand.on }}{{/if}}{{/if}}", "line 1: {{/if}} without {{#if}}"),
+            ("a\n\nb {{ brand.name", "line 3: unclosed {{"),
+            ("a\n{{ brand name }}", "line 2: unknown tag {{ brand name }}"),
+            ("a\n\n{{/if}}", "line 3: {{/if}} without {{#if}}"),
+            ("{{#if brand.on }}x", "unclosed {{#if}}"),
+            ("{{#if brand.on }}{{#if brand.off }}x{{/if}}", "unclosed {{#if}}"),
+            ("{{ brand name }}", "line 1: unknown tag {{ brand name }}"),
+            ("{{#if }}x{{/if}}", "line 1: unknown tag {{ #if }}"),
+            ("{{ brand..name }}", "line 1: unknown tag {{ brand..name }}"),
+        ];
+        for (source, error) in cases {
+            assert_eq!(Template::compile(source).err().as_deref(), Some(error), "{}", source);
+        }
+    }
+
+    #[test]
+    fn stops_includes_nested_too_deep() {
+        let root = fixture(
+            "depth",
+            &[
+                ("loop.html", "x{{> loop.html }}"),
+                ("a.html", "{{> b.html }}"),
+                ("b.html", "{{> a.html }}"),
+                ("chain.html", "{{> chain1.html }}"),
+                ("chain1.html", "{{> chain2.html }}"),
+                ("chain2.html", "done"),
+            ],
+        );
+        let loader = CachedLoader::new(&root);
+
+        for source in ["{{> loop.html }}", "{{> a.html }}"] {
+            let error = render(source, &loader).unwrap_err();
+            assert!(error.starts_with("includes nested deeper than 8"), "{}", error);
+        }
+        assert_eq!(render("{{> chain.html }}", &loader).unwrap(), "done");
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+// ----- Imports ----- //
+
+use std::{
+    fmt,
+    fs,
+    io,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use rustls::{
+    ServerConfig,
+    crypto::ring::{default_provider, sign::any_supported_type},
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+use crate::sys_core::{core_config::TlsConfig, core_stream::ClientStream};
+
+// ----- Structs ----- //
+
+/// Wraps accepted sockets in TLS sessions sharing one rustls config.
+pub struct TlsAcceptor {
+    acceptor: tokio_rustls::TlsAcceptor,
+}
+
+/// Serves the certificate from `cert_path`/`key_path` and reloads it when
+/// either file's mtime changes, so renewed certificates are picked up by new
+/// connections without a restart. A broken replacement keeps the old pair.
+struct ReloadingCertResolver {
+    cert_path: String,
+    key_path: String,
+    check_interval: Duration,
+    current: RwLock<LoadedCert>,
+    last_check: Mutex<Instant>,
+}
+
+struct LoadedCert {
+    key: Arc<CertifiedKey>,
+    mtimes: (Option<SystemTime>, Option<SystemTime>),
+}
+
+// ----- Implementations ----- //
+
+impl TlsAcceptor {
+    pub fn new(tls: &TlsConfig) -> Result<Self, String> {
+        let resolver = ReloadingCertResolver {
+            cert_path: tls.cert_path.clone(),
+            key_path: tls.key_path.clone(),
+            check_interval: Duration::from_secs(tls.reload_check_secs),
+            current: RwLock::new(load_cert(&tls.cert_path, &tls.key_path)?),
+            last_check: Mutex::new(Instant::now()),
+        };
+
+        let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("TLS protocol setup failed: {}", e))?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(resolver));
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        Ok(Self {
+            acceptor: tokio_rustls::TlsAcceptor::from(Arc::new(config)),
+        })
+    }
+
+    /// Run the server side of the handshake. Called from the connection's
+    /// own task, never the accept loop, so a slow client only stalls itself.
+    pub async fn accept(&self, stream: TcpStream) -> io::Result<ClientStream> {
+        let stream = self.acceptor.accept(stream).await?;
+        Ok(ClientStream::Tls(Box::new(stream)))
+    }
+}
+
+impl ReloadingCertResolver {
+    fn reload_if_changed(&self) {
+        {
+            let mut last_check = self.last_check.lock().unwrap();
+            if last_check.elapsed() < self.check_interval {
+                return;
+            }
+            *last_check = Instant::now();
+        }
+
+        let mtimes = (file_mtime(&self.cert_path), file_mtime(&self.key_path));
+        if self.current.read().unwrap().mtimes == mtimes {
+            return;
+        }
+
+        match load_cert(&self.cert_path, &self.key_path) {
+            Ok(loaded) => {
+                info!("Reloaded certificate from {}", self.cert_path);
+                *self.current.write().unwrap() = loaded;
+            }
+            Err(e) => {
+                warn!("Certificate reload failed, keeping previous: {}", e);
+                // Remember the mtimes so a broken file is not re-read on every handshake
+                self.current.write().unwrap().mtimes = mtimes;
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.reload_if_changed();
+        Some(Arc::clone(&self.current.read().unwrap().key))
+    }
+}
+
+impl fmt::Debug for ReloadingCertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadingCertResolver")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish()
+    }
+}
+
+// ----- Helpers ----- //
+
+fn load_cert(cert_path: &str, key_path: &str) -> Result<LoadedCert, String> {
+    let mtimes = (file_mtime(cert_path), file_mtime(key_path));
+
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read certificate {}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", cert_path));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("Failed to read private key {}: {}", key_path, e))?;
+    let signing_key = any_supported_type(&key)
+        .map_err(|e| format!("Unsupported private key {}: {}", key_path, e))?;
+
+    Ok(LoadedCert {
+        key: Arc::new(CertifiedKey::new(certs, signing_key)),
+        mtimes,
+    })
+}
+
+fn file_mtime(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
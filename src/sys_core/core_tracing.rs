@@ -0,0 +1,73 @@
+// ----- Imports ----- //
+
+use std::io::{self, IsTerminal};
+
+use tracing::{Span, field::Empty, info_span};
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+use crate::sys_core::{
+    core_config::{LogFormat, LoggingConfig},
+    HttpRequest,
+};
+
+// ----- Constants ----- //
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// ----- Setup ----- //
+
+/// Install the global subscriber. `RUST_LOG` takes precedence over the
+/// configured level so a single run can be made more verbose.
+pub fn init_tracing(config: &LoggingConfig) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(&config.level));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(io::stdout().is_terminal()); // No colour codes in redirected logs
+
+    match config.format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+    }
+}
+
+// ----- Request Spans ----- //
+
+/// The incoming `X-Request-Id` when it looks like a token, so IDs assigned by
+/// a proxy carry through; a fresh UUID otherwise.
+pub fn request_id_for(request: &HttpRequest) -> String {
+    request
+        .header(REQUEST_ID_HEADER)
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Span wrapping one request. `route` and `session_id` are filled in once
+/// routing and the session handler know them.
+pub fn request_span(request: &HttpRequest, request_id: &str) -> Span {
+    info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method,
+        path = %request.path,
+        route = Empty,
+        session_id = Empty,
+    )
+}
+
+/// Tag the current request with the session it acts on, so every line
+/// logged for the rest of the request carries it.
+pub fn record_session_id(session_id: &str) {
+    Span::current().record("session_id", session_id);
+}
@@ -0,0 +1,415 @@
+// ----- Imports ----- //
+
+use std::{
+    io::{self, ErrorKind},
+    time::{Duration, Instant},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use sha1::{Digest, Sha1};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    time,
+};
+use tracing::{debug, warn};
+
+use crate::sys_core::{
+    HttpRequest, HttpResponse, StatusCode,
+    core_cors::is_origin_allowed,
+    core_responses::{response_method_not_allowed, response_not_found, response_status},
+    core_router::{RouteMatch, WebSocketHandler, get_router},
+    core_shutdown::is_shutting_down,
+    core_stream::{ClientStream, write_timed},
+};
+
+// ----- Constants ----- //
+
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_MESSAGE_BYTES: usize = 64 * 1024; // Chat messages, not file transfers
+const FRAME_TIMEOUT: Duration = Duration::from_secs(10); // Rest of a frame once it has started
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+const PING_INTERVAL: Duration = Duration::from_secs(30); // Keeps proxies from dropping idle sockets
+
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_POLICY_VIOLATION: u16 = 1008;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+// ----- Structs ----- //
+
+/// Server side of an upgraded connection (RFC 6455). Pings, pongs and the
+/// close handshake are handled internally; handlers only see messages.
+pub struct WebSocket {
+    reader: BufReader<ClientStream>,
+    fragments: Option<(u8, Vec<u8>)>, // Opcode and payload of an unfinished message
+    last_seen: Instant,
+    closed: bool,
+}
+
+pub enum Message {
+    Text(String),
+    /// A binary message, whose content is dropped: handlers that only speak
+    /// text close with `CLOSE_UNSUPPORTED_DATA`.
+    Binary,
+}
+
+/// Result of waiting for the next message.
+pub enum Incoming {
+    Message(Message),
+    /// Nothing arrived within the wait; a chance for periodic work.
+    Idle,
+    /// The peer closed, the connection failed or the server is shutting down.
+    Closed,
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+enum FrameError {
+    Io(io::Error),
+    Protocol(&'static str),
+    TooBig,
+}
+
+// ----- Handshake ----- //
+
+pub fn is_upgrade_request(request: &HttpRequest) -> bool {
+    request
+        .header("Upgrade")
+        .is_some_and(|u| u.trim().eq_ignore_ascii_case("websocket"))
+}
+
+/// Validate an upgrade request against the WebSocket routes. On success the
+/// caller sends the returned 101 and hands the connection to the handler;
+/// otherwise the error response is sent like any other.
+pub fn handshake(request: &mut HttpRequest) -> Result<(WebSocketHandler, HttpResponse), HttpResponse> {
+    let handler = match get_router().dispatch(request) {
+        RouteMatch::Upgrade(handler) => handler,
+        RouteMatch::MethodNotAllowed(allowed) => return Err(response_method_not_allowed(&allowed)),
+        _ => return Err(response_not_found("No WebSocket endpoint at this path")),
+    };
+
+    let has_upgrade_token = request
+        .header("Connection")
+        .is_some_and(|c| c.split(',').any(|t| t.trim().eq_ignore_ascii_case("upgrade")));
+    if !has_upgrade_token {
+        return Err(response_status(StatusCode::BadRequest, "Missing Connection: Upgrade"));
+    }
+
+    if request.header("Sec-WebSocket-Version").map(str::trim) != Some("13") {
+        return Err(
+            response_status(StatusCode::UpgradeRequired, "Unsupported WebSocket version")
+                .with_header("Sec-WebSocket-Version", "13"),
+        );
+    }
+
+    let key = request.header("Sec-WebSocket-Key").unwrap_or("").trim();
+    if STANDARD.decode(key).ok().map(|k| k.len()) != Some(16) {
+        return Err(response_status(StatusCode::BadRequest, "Invalid Sec-WebSocket-Key"));
+    }
+
+    if !is_origin_allowed(request) {
+        warn!(
+            "Rejected WebSocket origin {} for {}",
+            request.header("Origin").unwrap_or(""),
+            request.path
+        );
+        return Err(response_status(StatusCode::Forbidden, "Origin not allowed"));
+    }
+
+    let response = HttpResponse::new(StatusCode::SwitchingProtocols)
+        .with_header("Upgrade", "websocket")
+        .with_header("Connection", "Upgrade")
+        .with_header("Sec-WebSocket-Accept", accept_key(key));
+    Ok((handler, response))
+}
+
+/// `Sec-WebSocket-Accept`: base64 of the SHA-1 of the key and the RFC GUID.
+fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(HANDSHAKE_GUID.as_bytes());
+    STANDARD.encode(hasher.finalize())
+}
+
+/// Close codes a peer may send: the assigned 1xxx codes, minus 1005, 1006
+/// and 1015 which only report local conditions, and the 3xxx/4xxx ranges
+/// for libraries and applications.
+fn is_valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
+
+// ----- Implementations ----- //
+
+impl WebSocket {
+    /// Take over a connection after the 101 was written. The reader may
+    /// already hold the first frames if the client sent them early.
+    pub fn new(reader: BufReader<ClientStream>) -> Self {
+        Self {
+            reader,
+            fragments: None,
+            last_seen: Instant::now(),
+            closed: false,
+        }
+    }
+
+    /// Wait up to `wait` for the next complete message.
+    pub async fn read(&mut self, wait: Duration) -> Incoming {
+        loop {
+            if self.closed {
+                return Incoming::Closed;
+            }
+            if is_shutting_down() {
+                self.close(CLOSE_GOING_AWAY, "Server shutting down").await;
+                return Incoming::Closed;
+            }
+
+            // Wait for the start of a frame without consuming anything, so a
+            // timeout here never leaves a frame half read
+            if self.reader.buffer().is_empty() {
+                match time::timeout(wait, self.reader.fill_buf()).await {
+                    Ok(Ok([])) | Ok(Err(_)) => {
+                        self.closed = true;
+                        return Incoming::Closed;
+                    }
+                    Ok(Ok(_)) => {}
+                    Err(_) => {
+                        self.ping_if_idle().await;
+                        return Incoming::Idle;
+                    }
+                }
+            }
+
+            let frame = match time::timeout(FRAME_TIMEOUT, self.read_frame()).await {
+                Ok(Ok(frame)) => frame,
+                Ok(Err(FrameError::Io(e))) => {
+                    if e.kind() != ErrorKind::UnexpectedEof {
+                        debug!("Read failed: {}", e);
+                    }
+                    self.closed = true;
+                    return Incoming::Closed;
+                }
+                Ok(Err(FrameError::Protocol(reason))) => {
+                    warn!("Protocol error: {}", reason);
+                    self.close(CLOSE_PROTOCOL_ERROR, reason).await;
+                    return Incoming::Closed;
+                }
+                Ok(Err(FrameError::TooBig)) => {
+                    self.close(CLOSE_TOO_BIG, "Message too large").await;
+                    return Incoming::Closed;
+                }
+                Err(_) => {
+                    debug!("Frame not completed in time");
+                    self.closed = true;
+                    return Incoming::Closed;
+                }
+            };
+            self.last_seen = Instant::now();
+
+            if let Some(incoming) = self.handle_frame(frame).await {
+                return incoming;
+            }
+        }
+    }
+
+    /// Control frames are answered here; data frames are collected until the
+    /// final fragment completes a message.
+    async fn handle_frame(&mut self, frame: Frame) -> Option<Incoming> {
+        let (opcode, payload) = match frame.opcode {
+            OP_PING => {
+                let _ = self.send_frame(OP_PONG, &frame.payload).await;
+                return None;
+            }
+            OP_PONG => return None,
+            OP_CLOSE => {
+                // Echo the peer's status code to complete the close handshake,
+                // unless it is one a peer may not send (RFC 6455 §7.4)
+                let code = match frame.payload.len() {
+                    0 => CLOSE_NORMAL,
+                    1 => CLOSE_PROTOCOL_ERROR,
+                    _ => match u16::from_be_bytes([frame.payload[0], frame.payload[1]]) {
+                        code if is_valid_close_code(code) => code,
+                        _ => CLOSE_PROTOCOL_ERROR,
+                    },
+                };
+                self.close(code, "").await;
+                return Some(Incoming::Closed);
+            }
+            OP_TEXT | OP_BINARY if self.fragments.is_some() => {
+                self.close(CLOSE_PROTOCOL_ERROR, "Expected a continuation frame").await;
+                return Some(Incoming::Closed);
+            }
+            OP_TEXT | OP_BINARY if !frame.fin => {
+                self.fragments = Some((frame.opcode, frame.payload));
+                return None;
+            }
+            OP_TEXT | OP_BINARY => (frame.opcode, frame.payload),
+            OP_CONTINUATION => {
+                let Some((opcode, mut payload)) = self.fragments.take() else {
+                    self.close(CLOSE_PROTOCOL_ERROR, "Unexpected continuation frame").await;
+                    return Some(Incoming::Closed);
+                };
+                payload.extend_from_slice(&frame.payload);
+                if payload.len() > MAX_MESSAGE_BYTES {
+                    self.close(CLOSE_TOO_BIG, "Message too large").await;
+                    return Some(Incoming::Closed);
+                }
+                if !frame.fin {
+                    self.fragments = Some((opcode, payload));
+                    return None;
+                }
+                (opcode, payload)
+            }
+            _ => {
+                self.close(CLOSE_PROTOCOL_ERROR, "Unknown opcode").await;
+                return Some(Incoming::Closed);
+            }
+        };
+
+        if opcode == OP_BINARY {
+            return Some(Incoming::Message(Message::Binary));
+        }
+        match String::from_utf8(payload) {
+            Ok(text) => Some(Incoming::Message(Message::Text(text))),
+            Err(_) => {
+                self.close(CLOSE_INVALID_DATA, "Text message is not valid UTF-8").await;
+                Some(Incoming::Closed)
+            }
+        }
+    }
+
+    async fn read_frame(&mut self) -> Result<Frame, FrameError> {
+        let mut head = [0u8; 2];
+        self.reader.read_exact(&mut head).await.map_err(FrameError::Io)?;
+
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0F;
+        if head[0] & 0x70 != 0 {
+            return Err(FrameError::Protocol("Reserved bits set"));
+        }
+        if head[1] & 0x80 == 0 {
+            return Err(FrameError::Protocol("Client frames must be masked"));
+        }
+
+        let len = match head[1] & 0x7F {
+            126 => {
+                let mut buf = [0u8; 2];
+                self.reader.read_exact(&mut buf).await.map_err(FrameError::Io)?;
+                u16::from_be_bytes(buf) as u64
+            }
+            127 => {
+                let mut buf = [0u8; 8];
+                self.reader.read_exact(&mut buf).await.map_err(FrameError::Io)?;
+                u64::from_be_bytes(buf)
+            }
+            n => n as u64,
+        };
+
+        if opcode & 0x8 != 0 && (len > 125 || !fin) {
+            return Err(FrameError::Protocol("Invalid control frame"));
+        }
+        if len > MAX_MESSAGE_BYTES as u64 {
+            return Err(FrameError::TooBig);
+        }
+
+        let mut mask = [0u8; 4];
+        self.reader.read_exact(&mut mask).await.map_err(FrameError::Io)?;
+        let mut payload = vec![0u8; len as usize];
+        self.reader.read_exact(&mut payload).await.map_err(FrameError::Io)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        Ok(Frame { fin, opcode, payload })
+    }
+
+    pub async fn send_text(&mut self, text: &str) -> io::Result<()> {
+        self.send_frame(OP_TEXT, text.as_bytes()).await
+    }
+
+    /// Send a close frame. The connection itself ends when the handler
+    /// returns; further reads report `Closed`.
+    pub async fn close(&mut self, code: u16, reason: &str) {
+        if self.closed {
+            return;
+        }
+        self.closed = true;
+
+        let mut payload = code.to_be_bytes().to_vec();
+        // Control frames carry at most 125 bytes
+        let mut end = reason.len().min(123);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        payload.extend_from_slice(&reason.as_bytes()[..end]);
+        let _ = self.send_frame(OP_CLOSE, &payload).await;
+    }
+
+    /// End the connection once the handler is done with it.
+    pub async fn finish(mut self) {
+        self.close(CLOSE_NORMAL, "").await;
+        self.reader.into_inner().close().await;
+    }
+
+    async fn ping_if_idle(&mut self) {
+        if self.last_seen.elapsed() >= PING_INTERVAL {
+            self.last_seen = Instant::now();
+            let _ = self.send_frame(OP_PING, &[]).await;
+        }
+    }
+
+    /// Server frames are never masked or fragmented.
+    async fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode);
+        match payload.len() {
+            len if len < 126 => frame.push(len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+
+        write_timed(self.reader.get_mut(), &frame, WRITE_TIMEOUT).await
+    }
+}
+
+// ----- Tests ----- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_only_close_codes_a_peer_may_send() {
+        for code in [1000, 1001, 1003, 1007, 1011, 1014, 3000, 4000, 4999] {
+            assert!(is_valid_close_code(code), "{}", code);
+        }
+        for code in [0, 999, 1004, 1005, 1006, 1015, 1016, 2000, 2999, 5000, u16::MAX] {
+            assert!(!is_valid_close_code(code), "{}", code);
+        }
+    }
+
+    #[test]
+    fn accept_key_matches_rfc_example() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+}
@@ -1,17 +1,41 @@
 // ----- Private Modules ----- //
 
+mod core_cors;
+mod core_limits;
+mod core_listener;
+mod core_mime;
+mod core_pool;
+mod core_request;
+mod core_router;
 mod core_routing;
 mod core_server;
+mod core_shutdown;
+mod core_static;
+mod core_stream;
+mod core_tls;
+mod core_tracing;
+mod core_status;
 
 // ----- Public Modules ----- //
 
 pub mod core_config;
+pub mod core_encoding;
+pub mod core_metrics;
+pub mod core_paths;
 pub mod core_responses;
+pub mod core_template;
+pub mod core_websocket;
 
 
 // ----- Exports ----- //
 
 pub use core_server::Server;
-pub use core_routing::HttpResponse;
+pub use core_shutdown::{install_signal_handler, is_shutting_down, shutdown_requested, spawn_tracked, wait_for_drain};
+pub use core_routing::{BodySender, HttpResponse};
+pub use core_request::HttpRequest;
+pub use core_status::StatusCode;
+pub use core_tracing::{init_tracing, record_session_id};
+pub use core_router::{Router, init_router};
+pub use core_routing::register_routes;
 
 pub use core_config::{load_config, get_config};
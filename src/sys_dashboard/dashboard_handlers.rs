@@ -1,98 +1,113 @@
 // ----- Imports ----- //
 
-use crate::sys_core::core_responses::response_ok;
-use crate::sys_core::HttpResponse;
-use crate::sys_db::db_sessions::init_database;
+use crate::sys_core::core_responses::{response_error, response_json, response_ok};
+use crate::sys_core::{HttpRequest, HttpResponse, Router, StatusCode};
+use crate::sys_db::db_sessions::with_database;
 use crate::sys_db::db_session_dashboard::*;
 use serde_json::json;
+use std::sync::Arc;
+
+// ----- Routes ----- //
+
+/// The dashboard page posts its filters as JSON, GET takes them as query params.
+pub fn register_routes(router: &mut Router) {
+    router
+        .get("/api/dashboard/stats", handle_dashboard_stats)
+        .post("/api/dashboard/stats", handle_dashboard_stats)
+        .get("/api/dashboard/top_companies", handle_dashboard_top_companies)
+        .post("/api/dashboard/top_companies", handle_dashboard_top_companies)
+        .get("/api/dashboard/tags", handle_dashboard_tags)
+        .post("/api/dashboard/tags", handle_dashboard_tags)
+        .get("/api/dashboard/solutions", handle_dashboard_solutions)
+        .post("/api/dashboard/solutions", handle_dashboard_solutions)
+        .get("/api/dashboard/sessions_by_day", handle_dashboard_sessions_by_day)
+        .post("/api/dashboard/sessions_by_day", handle_dashboard_sessions_by_day);
+}
 
 // ----- Dashboard Handlers ----- //
 
 /// GET /dashboard/stats
 /// Returns overall statistics about all sessions.
-pub fn handle_dashboard_stats() -> HttpResponse {
-    match init_database()
-        .and_then(|conn| get_session_stats(&conn))
-    {
+pub async fn handle_dashboard_stats(_request: Arc<HttpRequest>) -> HttpResponse {
+    match with_database("get_session_stats", get_session_stats).await {
         Ok(stats) => {
             let json = serde_json::to_string(&stats).unwrap_or_else(|_| "{}".to_string());
             response_ok("application/json; charset=utf-8", json.into_bytes())
         }
-        Err(e) => {
-            let err = json!({ "error": format!("Failed to get stats: {}", e) });
-            response_ok("application/json; charset=utf-8", err.to_string().into_bytes())
-        }
+        Err(e) => response_error(
+            StatusCode::InternalServerError,
+            &format!("Failed to get stats: {}", e),
+        ),
     }
 }
 
 /// GET /dashboard/top_companies
-/// Optional body: { "limit": 10 }
-pub fn handle_dashboard_top_companies(body: &str) -> HttpResponse {
-    let parsed: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
-    let limit = parsed.get("limit").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
+/// Optional body: { "limit": 10 } or query: ?limit=10
+pub async fn handle_dashboard_top_companies(request: Arc<HttpRequest>) -> HttpResponse {
+    let parsed: serde_json::Value = serde_json::from_str(request.body_str()).unwrap_or_default();
+    let limit = parsed
+        .get("limit")
+        .and_then(|v| v.as_u64())
+        .or_else(|| request.query_param("limit").and_then(|v| v.parse().ok()))
+        .unwrap_or(10) as usize;
 
-    match init_database()
-        .and_then(|conn| get_top_companies(&conn, limit))
-    {
+    match with_database("get_top_companies", move |conn| get_top_companies(conn, limit)).await {
         Ok(rows) => {
             let json = serde_json::to_string(&rows).unwrap_or_else(|_| "[]".to_string());
             response_ok("application/json; charset=utf-8", json.into_bytes())
         }
-        Err(e) => {
-            let err = json!({ "error": format!("Failed to get top companies: {}", e) });
-            response_ok("application/json; charset=utf-8", err.to_string().into_bytes())
-        }
+        Err(e) => response_error(
+            StatusCode::InternalServerError,
+            &format!("Failed to get top companies: {}", e),
+        ),
     }
 }
 
 /// GET /dashboard/tags
-pub fn handle_dashboard_tags() -> HttpResponse {
-    match init_database()
-        .and_then(|conn| get_tag_frequencies(&conn))
-    {
+pub async fn handle_dashboard_tags(_request: Arc<HttpRequest>) -> HttpResponse {
+    match with_database("get_tag_frequencies", get_tag_frequencies).await {
         Ok(rows) => {
             let json = serde_json::to_string(&rows).unwrap_or_else(|_| "[]".to_string());
             response_ok("application/json; charset=utf-8", json.into_bytes())
         }
-        Err(e) => {
-            let err = json!({ "error": format!("Failed to get tag frequencies: {}", e) });
-            response_ok("application/json; charset=utf-8", err.to_string().into_bytes())
-        }
+        Err(e) => response_error(
+            StatusCode::InternalServerError,
+            &format!("Failed to get tag frequencies: {}", e),
+        ),
     }
 }
 
 /// GET /dashboard/solutions
-pub fn handle_dashboard_solutions() -> HttpResponse {
-    match init_database()
-        .and_then(|conn| get_solution_type_frequencies(&conn))
-    {
+pub async fn handle_dashboard_solutions(_request: Arc<HttpRequest>) -> HttpResponse {
+    match with_database("get_solution_type_frequencies", get_solution_type_frequencies).await {
         Ok(rows) => {
             let json = serde_json::to_string(&rows).unwrap_or_else(|_| "[]".to_string());
             response_ok("application/json; charset=utf-8", json.into_bytes())
         }
-        Err(e) => {
-            let err = json!({ "error": format!("Failed to get solution type frequencies: {}", e) });
-            response_ok("application/json; charset=utf-8", err.to_string().into_bytes())
-        }
+        Err(e) => response_error(
+            StatusCode::InternalServerError,
+            &format!("Failed to get solution type frequencies: {}", e),
+        ),
     }
 }
 
 /// GET /dashboard/sessions_by_day
-/// Optional body: { "days": 7 }
-pub fn handle_dashboard_sessions_by_day(body: &str) -> HttpResponse {
-    let parsed: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
-    let days = parsed.get("days").and_then(|v| v.as_i64()).unwrap_or(7);
+/// Optional body: { "days": 7 } or query: ?days=7
+pub async fn handle_dashboard_sessions_by_day(request: Arc<HttpRequest>) -> HttpResponse {
+    let parsed: serde_json::Value = serde_json::from_str(request.body_str()).unwrap_or_default();
+    let days = parsed
+        .get("days")
+        .and_then(|v| v.as_i64())
+        .or_else(|| request.query_param("days").and_then(|v| v.parse().ok()))
+        .unwrap_or(7);
 
-    match init_database()
-        .and_then(|conn| get_sessions_by_day(&conn, days))
-    {
+    match with_database("get_sessions_by_day", move |conn| get_sessions_by_day(conn, days)).await {
         Ok(rows) => {
-            let json = json!({ "sessions_by_day": rows });
-            response_ok("application/json; charset=utf-8", json.to_string().into_bytes())
-        }
-        Err(e) => {
-            let err = json!({ "error": format!("Failed to get sessions by day: {}", e) });
-            response_ok("application/json; charset=utf-8", err.to_string().into_bytes())
+            response_json(&json!({ "sessions_by_day": rows }))
         }
+        Err(e) => response_error(
+            StatusCode::InternalServerError,
+            &format!("Failed to get sessions by day: {}", e),
+        ),
     }
 }
@@ -144,7 +144,7 @@ pub fn get_tag_frequencies(conn: &Connection) -> Result<Vec<TagFrequency>> {
     let mut tag_counts: HashMap<String, usize> = HashMap::new();
 
     let mut stmt = conn.prepare("SELECT summary_tags FROM sessions;")?;
-    let rows = stmt.query_map([], |row| Ok(row.get::<_, Option<String>>(0)?))?;
+    let rows = stmt.query_map([], |row| row.get::<_, Option<String>>(0))?;
 
     for tag_string in rows.flatten().flatten() {
         for tag in tag_string.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
@@ -157,7 +157,7 @@ pub fn get_tag_frequencies(conn: &Connection) -> Result<Vec<TagFrequency>> {
         .map(|(tag, count)| TagFrequency { tag, count })
         .collect();
 
-    tags.sort_by(|a, b| b.count.cmp(&a.count));
+    tags.sort_by_key(|tag| std::cmp::Reverse(tag.count));
     Ok(tags)
 }
 
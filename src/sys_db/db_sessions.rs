@@ -2,6 +2,16 @@ use rusqlite::{params, Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::time::Instant;
+use tracing::{debug, info_span, warn};
+
+use crate::sys_core::core_metrics::Histogram;
+
+static DB_QUERY_SECONDS: Histogram = Histogram::new(
+    "charmline_db_query_duration_seconds",
+    "SQLite queries including opening the database, by operation and outcome.",
+    &["op", "outcome"],
+);
 
 /// Represents a stored session row
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,6 +65,34 @@ pub fn init_database() -> Result<Connection> {
     Ok(conn)
 }
 
+/// Open the database and run `query` on the blocking thread pool, keeping
+/// SQLite I/O off the async workers. `op` names the query in its span. A
+/// panic in `query` is resumed in the caller rather than turned into an
+/// SQLite error.
+pub async fn with_database<T, F>(op: &'static str, query: F) -> Result<T>
+where
+    F: FnOnce(&Connection) -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let span = info_span!("db", op);
+    tokio::task::spawn_blocking(move || {
+        span.in_scope(|| {
+            let started = Instant::now();
+            let result = init_database().and_then(|conn| query(&conn));
+            let outcome = if result.is_ok() { "ok" } else { "error" };
+            DB_QUERY_SECONDS.observe(&[op, outcome], started.elapsed());
+            let elapsed_ms = started.elapsed().as_millis() as u64;
+            match &result {
+                Ok(_) => debug!(elapsed_ms, "Query finished"),
+                Err(e) => warn!(elapsed_ms, "Query failed: {}", e),
+            }
+            result
+        })
+    })
+    .await
+    .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
 /// Insert or replace a session entry
 pub fn insert_session(conn: &Connection, session: &SessionRow) -> Result<()> {
     conn.execute(
@@ -0,0 +1,137 @@
+// ----- Imports ----- //
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serde_json::{Map, Value, json};
+use tracing::warn;
+
+use crate::{
+    sys_bot::{bot_instructions::check_instructions, bot_openai::check_openai},
+    sys_core::{HttpRequest, HttpResponse, Router, StatusCode, core_config::try_get_config, is_shutting_down},
+    sys_db::db_sessions::with_database,
+};
+
+// ----- Types ----- //
+
+/// Outcome of one readiness check; `None` when the check is turned off.
+type CheckResult = Option<Result<(), String>>;
+
+// ----- Routes ----- //
+
+/// Probes for service managers and load balancers. They live outside `/api/`
+/// so CORS policies never apply to them.
+pub fn register_routes(router: &mut Router) {
+    router
+        .get("/healthz", handle_healthz)
+        .get("/readyz", handle_readyz);
+}
+
+// ----- Probe Handlers ----- //
+
+/// GET /healthz
+/// Liveness only: answering at all means the process and its runtime are up.
+pub async fn handle_healthz(_request: Arc<HttpRequest>) -> HttpResponse {
+    HttpResponse::json(StatusCode::Ok, &json!({ "status": "ok" })).with_header("Cache-Control", "no-store")
+}
+
+/// GET /readyz
+/// Whether this instance can serve chats, with a breakdown per check:
+/// `{"status": "ready", "checks": {"database": {"status": "ok", "elapsed_ms": 2}, ...}}`.
+/// 200 when nothing failed, 503 otherwise. Draining for shutdown counts as
+/// not ready, so balancers stop sending new traffic first.
+pub async fn handle_readyz(_request: Arc<HttpRequest>) -> HttpResponse {
+    let (accepting, config, instructions, database, llm) = tokio::join!(
+        run_check(check_accepting()),
+        run_check(check_config()),
+        run_check(check_instruction_files()),
+        run_check(check_database()),
+        run_check(check_llm()),
+    );
+
+    let checks: Map<String, Value> = [
+        ("accepting", accepting),
+        ("config", config),
+        ("instructions", instructions),
+        ("database", database),
+        ("llm", llm),
+    ]
+    .into_iter()
+    .map(|(name, check)| (name.to_string(), check))
+    .collect();
+
+    let failed: Vec<&str> = checks
+        .iter()
+        .filter(|(_, check)| check["status"] == "error")
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let ready = failed.is_empty();
+    let status = if ready {
+        StatusCode::Ok
+    } else {
+        warn!("Not ready, failed checks: {}", failed.join(", "));
+        StatusCode::ServiceUnavailable
+    };
+
+    let json = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "checks": checks,
+    });
+    HttpResponse::json(status, &json).with_header("Cache-Control", "no-store")
+}
+
+// ----- Checks ----- //
+
+async fn check_accepting() -> CheckResult {
+    Some(if is_shutting_down() {
+        Err("Shutting down".to_string())
+    } else {
+        Ok(())
+    })
+}
+
+async fn check_config() -> CheckResult {
+    Some(match try_get_config() {
+        None => Err("Config not loaded".to_string()),
+        Some(config) if config.bot_apikey.trim().is_empty() => Err("Bot API key is empty".to_string()),
+        Some(_) => Ok(()),
+    })
+}
+
+async fn check_instruction_files() -> CheckResult {
+    let checked = tokio::task::spawn_blocking(check_instructions).await;
+    Some(checked.map_err(|e| e.to_string()).and_then(|result| result))
+}
+
+/// Opens the database the way every query does, then reads from it.
+async fn check_database() -> CheckResult {
+    let query = with_database("readiness", |conn| {
+        conn.query_row("SELECT COUNT(*) FROM sessions", [], |_| Ok(()))
+    });
+    Some(query.await.map_err(|e| e.to_string()))
+}
+
+async fn check_llm() -> CheckResult {
+    let health = &try_get_config()?.health;
+    if !health.check_llm {
+        return None;
+    }
+    Some(check_openai(Duration::from_secs(health.llm_timeout_secs)).await)
+}
+
+// ----- Helpers ----- //
+
+async fn run_check(check: impl Future<Output = CheckResult>) -> Value {
+    let started = Instant::now();
+    let result = check.await;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Some(Ok(())) => json!({ "status": "ok", "elapsed_ms": elapsed_ms }),
+        Some(Err(error)) => json!({ "status": "error", "error": error, "elapsed_ms": elapsed_ms }),
+        None => json!({ "status": "skipped" }),
+    }
+}
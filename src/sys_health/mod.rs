@@ -0,0 +1,3 @@
+mod health_handlers;
+
+pub use health_handlers::register_routes;
@@ -1,22 +1,132 @@
 // ----- Imports ----- //
 
 use std::{
-    collections::HashMap,
-    env,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env, fs,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+
+use crate::sys_core::{
+    core_config::SymlinkPolicy,
+    core_encoding::{Encoding, compress},
+    core_metrics::{Counter, register_gauge},
+    core_paths::{is_hidden_name, normalize_static_path},
+    core_template::Template,
+};
+
+// ----- Constants ----- //
+
+pub const DEFAULT_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Built with the `embed-static` feature: `static/` is part of the binary.
+pub const EMBEDDED: bool = cfg!(feature = "embed-static");
+
+// ----- Embedded Files ----- //
+
+// `EMBEDDED_FILES`, name → content sorted by name, generated by build.rs.
+// Empty unless built with `embed-static`.
+include!(concat!(env!("OUT_DIR"), "/embedded_static.rs"));
+
+fn embedded_file(filename: &str) -> Option<&'static [u8]> {
+    EMBEDDED_FILES
+        .binary_search_by(|(name, _)| (*name).cmp(filename))
+        .ok()
+        .map(|index| EMBEDDED_FILES[index].1)
+}
+
+// ----- Metrics ----- //
+
+static CACHE_LOOKUPS: Counter = Counter::new(
+    "charmline_static_cache_lookups_total",
+    "CachedLoader lookups: hit, miss (including files that do not exist) or streamed from disk.",
+    &["result"],
+);
+
+static CACHE_EVICTIONS: Counter = Counter::new(
+    "charmline_static_cache_evictions_total",
+    "Files dropped from the static cache to stay within its byte budget.",
+    &[],
+);
+
+// ----- Global Cache Statistics ----- //
+
+static CACHE_STATS: OnceLock<Arc<CacheStats>> = OnceLock::new();
+
+pub fn get_cache_stats() -> Option<&'static CacheStats> {
+    CACHE_STATS.get().map(Arc::as_ref)
+}
+
 // ----- Structures ----- //
 
 #[derive(Clone)]
 pub struct CachedFile {
     pub bytes: Vec<u8>,
+    pub etag: String,                  // Strong validator, quoted content hash
+    pub modified: Option<SystemTime>, // File mtime when it entered the cache
+}
+
+/// A file over the per-file threshold, read from disk for every response
+/// instead of taking up the cache.
+#[derive(Clone)]
+pub struct DiskFile {
+    pub path: PathBuf,
+    pub etag: String, // From size and mtime, since hashing means reading it all
+    pub modified: Option<SystemTime>,
+}
+
+pub enum LoadedFile {
+    Cached(CachedFile),
+    Disk(DiskFile),
+}
+
+/// Occupancy and effectiveness of the static file cache.
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    pub max_bytes: usize,
+    pub max_file_bytes: usize,
+    pub bytes: AtomicUsize,
+    pub entries: AtomicUsize,
+    pub hits: AtomicU64,
+    pub misses: AtomicU64,
+    pub evictions: AtomicU64,
+    pub streamed: AtomicU64,
 }
 
 pub struct CachedLoader {
-    cache: Arc<Mutex<HashMap<String, CachedFile>>>,
-    pub root_dir: PathBuf,
+    cache: Mutex<LruCache>,
+    pub root_dir: Option<PathBuf>, // Files on disk; in embedded builds, the overrides
+    embedded: bool,                 // Fall back to the files compiled into the binary
+    revalidate: bool,      // Development: check each hit against the file on disk
+    symlinks: SymlinkPolicy,
+    max_file_bytes: usize, // Larger files are streamed from disk
+    stats: Arc<CacheStats>,
+}
+
+/// Files and their compressed variants, evicted least recently used first
+/// once their combined size passes `max_bytes`.
+struct LruCache {
+    entries: HashMap<String, CacheEntry>,
+    recency: BTreeMap<u64, String>, // Last use → key, oldest first
+    clock: u64,
+    bytes: usize,
+    max_bytes: usize,
+}
+
+struct CacheEntry {
+    file: CachedFile,
+    embedded: bool,
+    encoded: HashMap<Encoding, Vec<u8>>,
+    template: Option<Arc<Template>>,
+    sources: Vec<(String, String)>, // Rendered pages: name and ETag of each file used
+    last_used: u64,
 }
 
 // ----- Implementations ----- //
@@ -24,48 +134,571 @@ pub struct CachedLoader {
 impl CachedLoader {
     // Initialize a new CachedLoader with a specified base directory
     pub fn new(base_dir: impl AsRef<Path>) -> Self {
-        let exe_path = env::current_exe().expect("Failed to get current executable path");
-        let exe_dir = exe_path
-            .parent()
-            .expect("Executable should have a parent directory");
-        let root_dir = exe_dir.join(base_dir.as_ref());
-
-        println!(
-            "[CachedLoader] Executable dir: {}\n[CachedLoader] Base dir argument: {}\n[CachedLoader] Resolved root_dir: {}",
-            exe_dir.display(),
-            base_dir.as_ref().display(),
-            root_dir.display()
+        Self::with_sources(Some(exe_relative(base_dir.as_ref())), false)
+    }
+
+    /// Serve the files compiled into the binary. Files in `override_dir`,
+    /// relative to the executable, take their place when present.
+    pub fn embedded(override_dir: Option<&str>) -> Self {
+        let root_dir = override_dir.map(|dir| exe_relative(Path::new(dir)));
+        info!(
+            override_dir = ?root_dir.as_ref().map(|dir| dir.display().to_string()),
+            "Serving {} embedded static files",
+            EMBEDDED_FILES.len()
         );
+        Self::with_sources(root_dir, true)
+    }
 
+    fn with_sources(root_dir: Option<PathBuf>, embedded: bool) -> Self {
         Self {
-            cache: Arc::new(Mutex::new(HashMap::new())),
+            cache: Mutex::new(LruCache::new(DEFAULT_CACHE_BYTES)),
             root_dir,
+            embedded,
+            revalidate: false,
+            symlinks: SymlinkPolicy::WithinRoot,
+            max_file_bytes: DEFAULT_CACHE_BYTES,
+            stats: Arc::new(CacheStats {
+                max_bytes: DEFAULT_CACHE_BYTES,
+                max_file_bytes: DEFAULT_CACHE_BYTES,
+                ..Default::default()
+            }),
         }
     }
 
-    // Load a file from the cache or filesystem
-    pub fn load(&self, filename: &str) -> Option<CachedFile> {
-        if let Some(cached) = self.cache.lock().unwrap().get(filename).cloned() {
-            return Some(cached);
+    /// Development mode: every cache hit is checked against the file's mtime
+    /// and size, so edits show up on the next request without a restart.
+    pub fn revalidating(mut self, revalidate: bool) -> Self {
+        self.revalidate = revalidate;
+        self
+    }
+
+    /// Which symlinks below `root_dir` may be followed to a file.
+    pub fn with_symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Cap the cache at `max_bytes`. Files above `max_file_bytes`, or above
+    /// the whole budget, are streamed from disk rather than cached.
+    pub fn with_budget(mut self, max_bytes: usize, max_file_bytes: Option<usize>) -> Self {
+        self.max_file_bytes = max_file_bytes.unwrap_or(max_bytes).min(max_bytes);
+        self.cache = Mutex::new(LruCache::new(max_bytes));
+        self.stats = Arc::new(CacheStats {
+            max_bytes,
+            max_file_bytes: self.max_file_bytes,
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Report this loader's statistics on `/api/server/cache` and `/metrics`.
+    /// Only the first loader to publish is reported.
+    pub fn publish_stats(&self) {
+        if CACHE_STATS.set(Arc::clone(&self.stats)).is_err() {
+            return;
         }
+        register_gauge(
+            "charmline_static_cache_bytes",
+            "Bytes held by the static cache, compressed variants included.",
+            || get_cache_stats().map_or(0.0, |s| s.bytes.load(Ordering::Relaxed) as f64),
+        );
+        register_gauge(
+            "charmline_static_cache_entries",
+            "Files held by the static cache.",
+            || get_cache_stats().map_or(0.0, |s| s.entries.load(Ordering::Relaxed) as f64),
+        );
+    }
 
-        // Directly join the root_dir with the filename — no cleaning needed
-        let path = self.root_dir.join(filename);
+    /// Read every file under `root_dir`, and every embedded file, into the
+    /// cache so the first request for each does not hit the disk. Stops
+    /// adding once the budget is full.
+    pub fn preload(&self) {
+        let mut files = Vec::new();
+        if let Some(root_dir) = &self.root_dir {
+            collect_files(root_dir, "", &mut files);
+        }
+        if self.embedded {
+            files.extend(EMBEDDED_FILES.iter().map(|(name, _)| name.to_string()));
+        }
+        files.retain(|name| name.split('/').all(|segment| !is_hidden_name(segment)));
+        let files: BTreeSet<String> = files.into_iter().collect();
+
+        let mut loaded = 0;
+        for name in &files {
+            if let Some(LoadedFile::Cached(_)) = self.load(name) {
+                loaded += 1;
+            }
+            if self.stats.evictions.load(Ordering::Relaxed) > 0 {
+                warn!("Static cache budget reached, preloaded {} of {} files", loaded, files.len());
+                return;
+            }
+        }
+        let bytes = self.cache.lock().unwrap().bytes;
+        info!("Preloaded {} static files ({} bytes)", loaded, bytes);
+    }
 
-        match std::fs::read(&path) {
-            Ok(bytes) => {
-                let cached_file = CachedFile {
-                    bytes: bytes.clone(),
-                };
-                self.cache
-                    .lock()
-                    .unwrap()
-                    .insert(filename.to_string(), cached_file.clone());
-                Some(cached_file)
+    // Load a file from the cache, the filesystem or the binary, in that order.
+    // `filename` must come from `resolve_static_path`; other names are refused.
+    // It is already decoded, so it is only checked for being normalized.
+    pub fn load(&self, filename: &str) -> Option<LoadedFile> {
+        if normalize_static_path(filename).as_deref() != Ok(filename) {
+            warn!("Refused unresolved static path {:?}", filename);
+            return None;
+        }
+        let path = self.disk_path(filename);
+
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(entry) = cache.get(filename) {
+                if !self.revalidate || !entry.is_stale(path.as_deref()) {
+                    let file = entry.file.clone();
+                    self.count_lookup("hit", &self.stats.hits);
+                    return Some(LoadedFile::Cached(file));
+                }
+                debug!("Reloading changed file {}", filename);
+                cache.remove(filename);
+                self.update_occupancy(&cache);
             }
-            Err(_e) => {
-                None
+        }
+
+        let on_disk = path.and_then(|path| match fs::metadata(&path) {
+            Ok(meta) if meta.is_file() => Some((path, meta)),
+            _ => None,
+        });
+        let Some((path, meta)) = on_disk else {
+            return self.load_embedded(filename);
+        };
+        let modified = meta.modified().ok();
+
+        if meta.len() > self.max_file_bytes as u64 {
+            self.count_lookup("streamed", &self.stats.streamed);
+            return Some(LoadedFile::Disk(DiskFile {
+                etag: metadata_etag(meta.len(), modified),
+                path,
+                modified,
+            }));
+        }
+
+        self.count_lookup("miss", &self.stats.misses);
+        let bytes = fs::read(&path).ok()?;
+        let file = CachedFile {
+            etag: content_etag(&bytes),
+            bytes,
+            modified,
+        };
+
+        self.insert(filename, file.clone(), false);
+        Some(LoadedFile::Cached(file))
+    }
+
+    // Embedded files over the per-file threshold are served straight from
+    // the binary's copy without entering the cache
+    fn load_embedded(&self, filename: &str) -> Option<LoadedFile> {
+        self.count_lookup("miss", &self.stats.misses);
+        let bytes = embedded_file(filename).filter(|_| self.embedded)?;
+        let file = CachedFile {
+            etag: content_etag(bytes),
+            bytes: bytes.to_vec(),
+            modified: None,
+        };
+
+        if bytes.len() <= self.max_file_bytes {
+            self.insert(filename, file.clone(), true);
+        }
+        Some(LoadedFile::Cached(file))
+    }
+
+    fn insert(&self, filename: &str, file: CachedFile, embedded: bool) {
+        let mut cache = self.cache.lock().unwrap();
+        let evicted = cache.insert(filename, file, embedded);
+        self.count_evictions(evicted);
+        self.update_occupancy(&cache);
+    }
+
+    // Current ETag of a file, without copying it out of the cache
+    fn current_etag(&self, filename: &str) -> Option<String> {
+        if !self.revalidate
+            && let Some(entry) = self.cache.lock().unwrap().get(filename)
+        {
+            return Some(entry.file.etag.clone());
+        }
+        match self.load(filename)? {
+            LoadedFile::Cached(file) => Some(file.etag),
+            LoadedFile::Disk(file) => Some(file.etag),
+        }
+    }
+
+    // Load a compressed variant of a loaded file, compressing it on first
+    // request and again whenever the file's content has changed
+    pub fn load_encoded(&self, filename: &str, file: &CachedFile, encoding: Encoding) -> Vec<u8> {
+        if let Some(entry) = self.cache.lock().unwrap().get(filename)
+            && entry.file.etag == file.etag
+            && let Some(bytes) = entry.encoded.get(&encoding)
+        {
+            return bytes.clone();
+        }
+
+        let bytes = compress(&file.bytes, encoding);
+        let mut cache = self.cache.lock().unwrap();
+        let evicted = cache.insert_encoded(filename, &file.etag, encoding, bytes.clone());
+        self.count_evictions(evicted);
+        self.update_occupancy(&cache);
+        bytes
+    }
+
+    // Where `filename` lives on disk, unless reaching it breaks the symlink
+    // policy. The root is canonicalized on each call, so it may itself be
+    // reached through a symlink or appear after startup.
+    fn disk_path(&self, filename: &str) -> Option<PathBuf> {
+        let root = fs::canonicalize(self.root_dir.as_ref()?).ok()?;
+        let path = root.join(filename);
+
+        match self.symlinks {
+            SymlinkPolicy::Follow => Some(path),
+            SymlinkPolicy::WithinRoot => {
+                let real = fs::canonicalize(&path).ok()?;
+                if real.starts_with(&root) {
+                    Some(real)
+                } else {
+                    warn!("Refused {}: resolves outside the static directory", filename);
+                    None
+                }
+            }
+            SymlinkPolicy::Deny => {
+                let mut current = root;
+                for segment in filename.split('/') {
+                    current.push(segment);
+                    if fs::symlink_metadata(&current).ok()?.file_type().is_symlink() {
+                        warn!("Refused {}: symlinks are not followed", filename);
+                        return None;
+                    }
+                }
+                Some(current)
+            }
+        }
+    }
+
+    // Parse a loaded file as a template on first request and again whenever
+    // its content has changed
+    pub fn load_template(&self, filename: &str, file: &CachedFile) -> Result<Arc<Template>, String> {
+        if let Some(entry) = self.cache.lock().unwrap().get(filename)
+            && entry.file.etag == file.etag
+            && let Some(template) = &entry.template
+        {
+            return Ok(Arc::clone(template));
+        }
+
+        let source = std::str::from_utf8(&file.bytes).map_err(|_| format!("{}: not UTF-8", filename))?;
+        let template = Arc::new(Template::compile(source).map_err(|e| format!("{}: {}", filename, e))?);
+        let mut cache = self.cache.lock().unwrap();
+        let evicted = cache.insert_template(filename, &file.etag, Arc::clone(&template));
+        self.count_evictions(evicted);
+        self.update_occupancy(&cache);
+        Ok(template)
+    }
+
+    /// A loaded template with its placeholders filled in, validated by the
+    /// ETag of the result. No `Last-Modified`: includes and config feed into
+    /// it too. Cached under `rendered_key`, compressed variants included,
+    /// until the template or one of its includes changes.
+    pub fn load_rendered(&self, filename: &str, file: &CachedFile) -> Result<CachedFile, String> {
+        let key = rendered_key(filename);
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(&key)
+            .map(|entry| (entry.file.clone(), entry.sources.clone()));
+
+        if let Some((page, sources)) = cached
+            && sources.first().is_some_and(|(_, etag)| *etag == file.etag)
+            && sources[1..]
+                .iter()
+                .all(|(name, etag)| self.current_etag(name).as_ref() == Some(etag))
+        {
+            return Ok(page);
+        }
+
+        let mut sources = vec![(filename.to_string(), file.etag.clone())];
+        let bytes = self
+            .load_template(filename, file)?
+            .render(self, &mut sources)
+            .map_err(|e| format!("{}: {}", filename, e))?;
+        let page = CachedFile {
+            etag: content_etag(&bytes),
+            bytes,
+            modified: None,
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        let evicted = cache.insert(&key, page.clone(), false);
+        if let Some(entry) = cache.entries.get_mut(&key) {
+            entry.sources = sources;
+        }
+        self.count_evictions(evicted);
+        self.update_occupancy(&cache);
+        Ok(page)
+    }
+
+    fn count_lookup(&self, result: &str, counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+        CACHE_LOOKUPS.inc(&[result]);
+    }
+
+    fn count_evictions(&self, evicted: usize) {
+        if evicted > 0 {
+            self.stats.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+            CACHE_EVICTIONS.add(&[], evicted as u64);
+        }
+    }
+
+    fn update_occupancy(&self, cache: &LruCache) {
+        self.stats.bytes.store(cache.bytes, Ordering::Relaxed);
+        self.stats.entries.store(cache.entries.len(), Ordering::Relaxed);
+    }
+}
+
+
+impl LruCache {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    // Look up an entry and mark it as the most recently used
+    fn get(&mut self, key: &str) -> Option<&CacheEntry> {
+        let entry = self.entries.get_mut(key)?;
+        self.clock += 1;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(self.clock, key.to_string());
+        entry.last_used = self.clock;
+        Some(entry)
+    }
+
+    // Returns how many entries were evicted to make room
+    fn insert(&mut self, key: &str, file: CachedFile, embedded: bool) -> usize {
+        self.remove(key);
+        self.clock += 1;
+        self.bytes += file.bytes.len();
+        self.recency.insert(self.clock, key.to_string());
+        self.entries.insert(
+            key.to_string(),
+            CacheEntry {
+                file,
+                embedded,
+                encoded: HashMap::new(),
+                template: None,
+                sources: Vec::new(),
+                last_used: self.clock,
+            },
+        );
+        self.evict_to_fit()
+    }
+
+    // Attach a compressed variant, unless the file changed in the meantime
+    fn insert_encoded(&mut self, key: &str, etag: &str, encoding: Encoding, bytes: Vec<u8>) -> usize {
+        let Some(entry) = self.entries.get_mut(key).filter(|e| e.file.etag == etag) else {
+            return 0;
+        };
+        self.bytes += bytes.len();
+        if let Some(previous) = entry.encoded.insert(encoding, bytes) {
+            self.bytes -= previous.len();
+        }
+        self.evict_to_fit()
+    }
+
+    // Attach a compiled template, unless the file changed in the meantime
+    fn insert_template(&mut self, key: &str, etag: &str, template: Arc<Template>) -> usize {
+        let Some(entry) = self.entries.get_mut(key).filter(|e| e.file.etag == etag) else {
+            return 0;
+        };
+        self.bytes += template.size();
+        if let Some(previous) = entry.template.replace(template) {
+            self.bytes -= previous.size();
+        }
+        self.evict_to_fit()
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+            self.bytes -= entry.size();
+        }
+    }
+
+    fn evict_to_fit(&mut self) -> usize {
+        let mut evicted = 0;
+        while self.bytes > self.max_bytes {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.size();
+                evicted += 1;
             }
         }
+        evicted
+    }
+}
+
+impl CacheEntry {
+    fn size(&self) -> usize {
+        self.file.bytes.len()
+            + self.encoded.values().map(Vec::len).sum::<usize>()
+            + self.template.as_ref().map_or(0, |template| template.size())
+    }
+
+    // Changed on disk since it was cached: different mtime or size, or
+    // deleted. An embedded file is stale once an override for it appears.
+    fn is_stale(&self, path: Option<&Path>) -> bool {
+        let meta = path.and_then(|path| fs::metadata(path).ok());
+        match meta {
+            _ if self.embedded => meta.is_some_and(|meta| meta.is_file()),
+            Some(meta) => meta.modified().ok() != self.file.modified || meta.len() != self.file.bytes.len() as u64,
+            None => true,
+        }
+    }
+}
+
+// ----- Helpers ----- //
+
+// Static paths are resolved next to the executable, not the working directory
+fn exe_relative(base_dir: &Path) -> PathBuf {
+    let exe_path = env::current_exe().expect("Failed to get current executable path");
+    let exe_dir = exe_path
+        .parent()
+        .expect("Executable should have a parent directory");
+    let root_dir = exe_dir.join(base_dir);
+
+    info!(
+        exe_dir = %exe_dir.display(),
+        base_dir = %base_dir.display(),
+        "Resolved root_dir: {}",
+        root_dir.display()
+    );
+    root_dir
+}
+
+// Relative paths (with `/` separators) of every file below `dir`
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Cannot read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => collect_files(&entry.path(), &format!("{}/", name), files),
+            Ok(_) => files.push(name),
+            Err(_) => {}
+        }
+    }
+}
+
+/// Cache key of the page rendered from template `filename`, for
+/// `load_encoded`. Never a static file's name, since those cannot contain `:`.
+pub fn rendered_key(filename: &str) -> String {
+    format!("rendered:{}", filename)
+}
+
+/// Strong ETag from the first 128 bits of the SHA-256 of the content.
+pub fn content_etag(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+// ETag for streamed files: size and mtime, as most servers do for large files
+fn metadata_etag(len: u64, modified: Option<SystemTime>) -> String {
+    let nanos = modified
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    format!("\"{:x}-{:x}\"", len, nanos)
+}
+
+// ----- Tests ----- //
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    // A static root beside a directory it must not leak:
+    // root/{index.html, .git/config, link_in, link_out, dir_out}, outside/secret.txt
+    fn fixture(name: &str) -> PathBuf {
+        let base = env::temp_dir().join(format!("charmline-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let (root, outside) = (base.join("root"), base.join("outside"));
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(root.join("index.html"), "index").unwrap();
+        fs::write(root.join(".git/config"), "secret").unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink(root.join("index.html"), root.join("link_in")).unwrap();
+        symlink(outside.join("secret.txt"), root.join("link_out")).unwrap();
+        symlink(&outside, root.join("dir_out")).unwrap();
+        base
+    }
+
+    fn loads(loader: &CachedLoader, filename: &str) -> bool {
+        loader.load(filename).is_some()
+    }
+
+    #[test]
+    fn within_root_follows_only_contained_symlinks() {
+        let base = fixture("within-root");
+        let loader = CachedLoader::new(base.join("root"));
+
+        assert!(loads(&loader, "index.html"));
+        assert!(loads(&loader, "link_in"));
+        assert!(!loads(&loader, "link_out"));
+        assert!(!loads(&loader, "dir_out/secret.txt"));
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn deny_refuses_every_symlink() {
+        let base = fixture("deny");
+        let loader = CachedLoader::new(base.join("root")).with_symlinks(SymlinkPolicy::Deny);
+
+        assert!(loads(&loader, "index.html"));
+        assert!(!loads(&loader, "link_in"));
+        assert!(!loads(&loader, "link_out"));
+        assert!(!loads(&loader, "dir_out/secret.txt"));
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn follow_allows_symlinks_out_of_the_root() {
+        let base = fixture("follow");
+        let loader = CachedLoader::new(base.join("root")).with_symlinks(SymlinkPolicy::Follow);
+
+        assert!(loads(&loader, "link_out"));
+        assert!(loads(&loader, "dir_out/secret.txt"));
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn refuses_unresolved_names_under_any_policy() {
+        let base = fixture("unresolved");
+        for policy in [SymlinkPolicy::Deny, SymlinkPolicy::WithinRoot, SymlinkPolicy::Follow] {
+            let loader = CachedLoader::new(base.join("root")).with_symlinks(policy);
+
+            assert!(!loads(&loader, "../outside/secret.txt"));
+            assert!(!loads(&loader, "/etc/passwd"));
+            assert!(!loads(&loader, "./index.html"));
+            assert!(!loads(&loader, ".git/config"));
+        }
+        fs::remove_dir_all(base).unwrap();
     }
 }
@@ -1,2 +1,3 @@
 pub mod session_state;
-pub mod session_handlers;
\ No newline at end of file
+pub mod session_handlers;
+pub mod session_socket;
\ No newline at end of file
@@ -1,29 +1,66 @@
 // ----- Imports ----- //
 
 use crate::{
-    sys_bot::{bot_instructions::get_instructions, bot_openai::ask_openai, bot_reply::BotReply},
+    sys_bot::{
+        bot_instructions::{INTRODUCTION_MESSAGE, SALES_INSTRUCTIONS, get_instructions},
+        bot_openai::{ask_openai, ask_openai_stream},
+        bot_reply::{BotReply, ReplyFilter},
+    },
     sys_core::{
-        HttpResponse,
-        core_responses::{response_not_found, response_ok},
+        BodySender, HttpRequest, HttpResponse, Router, StatusCode, record_session_id, spawn_tracked,
+        core_metrics::Counter,
+        core_responses::{response_error, response_json},
+    },
+    sys_session::{
+        session_socket::handle_session_socket,
+        session_state::{Session, SessionArtifact, SessionSummary, get_session_manager},
     },
-    sys_session::session_state::{Session, SessionArtifact, SessionSummary, get_session_manager},
-    sys_db::db_sessions::{SessionRow, init_database, insert_session}
+    sys_db::db_sessions::{SessionRow, get_all_sessions, get_session_by_id, insert_session, with_database}
 };
 
 use chrono::{DateTime, Utc};
 use serde_json::json;
-use std::{collections::HashMap, sync::MutexGuard, time::SystemTime};
+use std::{
+    collections::HashMap,
+    sync::{Arc, MutexGuard},
+    time::SystemTime,
+};
+use tracing::{Instrument, debug, info, info_span, instrument, warn};
+
+// ----- Routes ----- //
+
+pub fn register_routes(router: &mut Router) {
+    router
+        .get("/api/session/start", handle_session_start)
+        .post("/api/session/start", handle_session_start)
+        .post("/api/session/get", handle_session_get)
+        .post("/api/session/sendinput", handle_session_sendinput)
+        .get("/api/session/stream", handle_session_stream)
+        .post("/api/session/stream", handle_session_stream)
+        .get("/api/session/listartifacts", handle_session_list_artifacts)
+        .post("/api/session/listartifacts", handle_session_list_artifacts)
+        .post("/api/session/getartifact", handle_session_get_artifact)
+        .websocket("/api/session/ws/{id}", handle_session_socket)
+        .get("/api/sessions/{id}", handle_session_artifact_by_id);
+}
 
 // ----- Session Route Handlers ----- //
 
 const SESSION_TIMEOUT_SECS: u64 = 300; // 5 minutes
 
-pub fn handle_session_start() -> HttpResponse {
+static SUMMARY_JOBS: Counter = Counter::new(
+    "charmline_summary_jobs_total",
+    "Ended sessions summarised and saved, by outcome.",
+    &["outcome"],
+);
+
+pub async fn handle_session_start(_request: Arc<HttpRequest>) -> HttpResponse {
     let session = get_session_manager().create_session(SESSION_TIMEOUT_SECS);
-    let first_message = get_instructions("cfg/bots/msg_introduction.txt");
+    record_session_id(&session.session_id);
+    let first_message = get_instructions(INTRODUCTION_MESSAGE);
 
     let mut session = session;
-    session.session_chat = format!("Bot: {}", first_message.to_string());
+    session.session_chat = format!("Bot: {}", first_message);
 
     get_session_manager().update_session(session.clone());
 
@@ -33,19 +70,21 @@ pub fn handle_session_start() -> HttpResponse {
         "chat": first_message
     });
 
-    response_ok(
-        "application/json; charset=utf-8",
-        json.to_string().into_bytes(),
-    )
+    response_json(&json)
 }
 
-pub fn handle_session_get(body: &str) -> HttpResponse {
-    let parsed: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+pub async fn handle_session_get(request: Arc<HttpRequest>) -> HttpResponse {
+    let parsed: serde_json::Value = serde_json::from_str(request.body_str()).unwrap_or_default();
     let session_id = parsed
         .get("session_id")
         .and_then(|v| v.as_str())
         .unwrap_or("");
 
+    if session_id.is_empty() {
+        return response_error(StatusCode::BadRequest, "Missing session_id");
+    }
+    record_session_id(session_id);
+
     match get_session_manager().get_session(session_id) {
         Some(s) => {
             let json = json!({
@@ -53,90 +92,165 @@ pub fn handle_session_get(body: &str) -> HttpResponse {
                 "expires_in": s.time_remaining(),
                 "chat": s.session_chat
             });
-            response_ok(
-                "application/json; charset=utf-8",
-                json.to_string().into_bytes(),
-            )
+            response_json(&json)
         }
-        None => response_not_found("Session not found"),
+        None => response_error(StatusCode::NotFound, "Session not found"),
     }
 }
 
-pub fn handle_session_sendinput(body: &str) -> HttpResponse {
+pub async fn handle_session_sendinput(request: Arc<HttpRequest>) -> HttpResponse {
     // Parse input JSON
-    let input_data = InputData::from_json(body);
+    let input_data = InputData::from_json(request.body_str());
+    if input_data.session_id.is_empty() || input_data.input.trim().is_empty() {
+        return response_error(StatusCode::BadRequest, "Missing session_id or input");
+    }
+    record_session_id(&input_data.session_id);
 
-    let manager = get_session_manager();
-    let mut sessions = manager.sessions.lock().unwrap();
+    match process_input(&input_data.session_id, &input_data.input).await {
+        Ok(turn) => {
+            let json = json!({ "reply": turn.reply, "session_ended": turn.session_ended });
+            response_json(&json)
+        }
+        Err((status, msg)) => response_error(status, &msg),
+    }
+}
 
-    if let Some(session) = sessions.get_mut(&input_data.session_id) {
-        let system_prompt = get_instructions("cfg/bots/instructions_sales.txt");
-
-        let messages = vec![
-            ("system", system_prompt.as_str()),
-            ("user", session.session_chat.as_str()),
-            ("user", &input_data.input),
-        ];
-
-        match ask_openai(messages) {
-            Ok(reply) => {
-                let cleaned_reply = BotReply::parse_reply(&reply);
-
-                // Update chat history (keep full version including tags for internal context)
-                session.session_chat = format!(
-                    "{}\n\nUser: {}\nBot: {}\n",
-                    session.session_chat.trim_end(),
-                    &input_data.input,
-                    cleaned_reply.reply_string
-                );
-
-                // --- Handle ENDCALL logic ---
-                if cleaned_reply.is_endcall {
-                    let session_clone = session.clone();
-                    return end_convo(&mut sessions, &session_clone, cleaned_reply.reply_string);
-                }
+/// One user message answered by the bot.
+pub struct ChatTurn {
+    pub reply: String,
+    pub session_ended: bool,
+}
 
-                // Debug log the history
-                println!("--- Updated Session Chat ---\n{}", session.session_chat);
+/// Run one chat turn against the shared session state. Both the REST
+/// `sendinput` route and the session WebSocket go through here.
+#[instrument(name = "chat_turn", skip_all)]
+pub async fn process_input(session_id: &str, input: &str) -> Result<ChatTurn, (StatusCode, String)> {
+    let (system_prompt, chat) = turn_prompt(session_id)?;
+    let messages = vec![
+        ("system", system_prompt.as_str()),
+        ("user", chat.as_str()),
+        ("user", input),
+    ];
+
+    let reply = ask_openai(messages).await.map_err(openai_error)?;
+    complete_turn(session_id, input, &reply)
+}
 
-                // Normal continuation response
-                let json = json!({ "reply": cleaned_reply.reply_string, "session_ended": false });
-                response_ok(
-                    "application/json; charset=utf-8",
-                    json.to_string().into_bytes(),
-                )
-            }
+/// POST /api/session/stream (also GET with query parameters for `EventSource`)
+///
+/// Same input as `sendinput`, answered as Server-Sent Events: `delta` events
+/// carry reply text as it is generated, then one `done` event with the
+/// complete reply and `session_ended`, or an `error` event.
+pub async fn handle_session_stream(request: Arc<HttpRequest>) -> HttpResponse {
+    let input_data = InputData::from_request(&request);
+    if input_data.session_id.is_empty() || input_data.input.trim().is_empty() {
+        return response_error(StatusCode::BadRequest, "Missing session_id or input");
+    }
+    record_session_id(&input_data.session_id);
+    if get_session_manager().get_session(&input_data.session_id).is_none() {
+        return response_error(StatusCode::NotFound, "Session not found");
+    }
 
-            Err(err) => {
-                let json = json!({
-                    "error": format!("OpenAI Error: {}", err)
-                });
-                response_ok(
-                    "application/json; charset=utf-8",
-                    json.to_string().into_bytes(),
-                )
+    let (mut response, events) = HttpResponse::streaming("text/event-stream; charset=utf-8");
+
+    // Tracked, and kept running after a disconnect so the transcript stays complete
+    spawn_tracked(async move {
+        match stream_turn(&input_data, &events).await {
+            Ok(turn) => {
+                let done = json!({ "reply": turn.reply, "session_ended": turn.session_ended });
+                send_event(&events, "done", done).await;
             }
+            Err((_, msg)) => send_event(&events, "error", json!({ "error": msg })).await,
         }
-    } else {
-        response_not_found("Session not found")
-    }
+    });
+
+    response.set_header("Cache-Control", "no-cache");
+    response.set_header("X-Accel-Buffering", "no"); // Stop nginx from buffering events
+    response
 }
 
-pub fn handle_session_list_artifacts(_body: &str) -> HttpResponse {
-    // Connect to database
-    let conn = match crate::sys_db::db_sessions::init_database() {
-        Ok(c) => c,
-        Err(e) => {
-            return response_ok(
-                "application/json; charset=utf-8",
-                json!({ "error": format!("Failed to open database: {}", e) })
-                    .to_string()
-                    .into_bytes(),
-            );
+/// `process_input` with the reply streamed: control tags are filtered from
+/// the deltas, while the transcript update and [ENDCALL] handling happen
+/// once the full reply is in.
+#[instrument(name = "chat_turn", skip_all)]
+async fn stream_turn(input_data: &InputData, events: &BodySender) -> Result<ChatTurn, (StatusCode, String)> {
+    let (system_prompt, chat) = turn_prompt(&input_data.session_id)?;
+    let messages = vec![
+        ("system", system_prompt.as_str()),
+        ("user", chat.as_str()),
+        ("user", input_data.input.as_str()),
+    ];
+
+    let mut stream = ask_openai_stream(messages).await.map_err(openai_error)?;
+    let mut filter = ReplyFilter::new();
+    let mut reply = String::new();
+    while let Some(delta) = stream.next_delta().await.map_err(openai_error)? {
+        reply.push_str(&delta);
+        let text = filter.push(&delta);
+        if !text.is_empty() {
+            send_event(events, "delta", json!({ "text": text })).await;
         }
+    }
+
+    complete_turn(&input_data.session_id, &input_data.input, &reply)
+}
+
+/// Fails once the client is gone, which only stops the events.
+async fn send_event(events: &BodySender, event: &str, data: serde_json::Value) {
+    let frame = format!("event: {}\ndata: {}\n\n", event, data);
+    let _ = events.send(frame.into_bytes()).await;
+}
+
+/// System prompt and transcript for the next turn. Copied out so the
+/// sessions lock is not held while the bot answers.
+fn turn_prompt(session_id: &str) -> Result<(String, String), (StatusCode, String)> {
+    let Some(session) = get_session_manager().get_session(session_id) else {
+        return Err((StatusCode::NotFound, "Session not found".to_string()));
+    };
+    let system_prompt = get_instructions(SALES_INSTRUCTIONS);
+    Ok((system_prompt, session.session_chat))
+}
+
+/// Record the bot's raw reply on the session, ending it on [ENDCALL].
+fn complete_turn(session_id: &str, input: &str, reply: &str) -> Result<ChatTurn, (StatusCode, String)> {
+    let cleaned_reply = BotReply::parse_reply(reply);
+
+    let manager = get_session_manager();
+    let mut sessions = manager.sessions.lock().unwrap();
+
+    // The session may have expired or ended while the bot was answering
+    let Some(session) = sessions.get_mut(session_id) else {
+        return Err((StatusCode::NotFound, "Session not found".to_string()));
     };
 
-    match crate::sys_db::db_sessions::get_all_sessions(&conn) {
+    // Update chat history (keep full version including tags for internal context)
+    session.session_chat = format!(
+        "{}\n\nUser: {}\nBot: {}\n",
+        session.session_chat.trim_end(),
+        input,
+        cleaned_reply.reply_string
+    );
+
+    // --- Handle ENDCALL logic ---
+    if cleaned_reply.is_endcall {
+        let session_clone = session.clone();
+        end_convo(&mut sessions, &session_clone);
+    } else {
+        debug!("Updated session chat:\n{}", session.session_chat);
+    }
+
+    Ok(ChatTurn {
+        reply: cleaned_reply.reply_string,
+        session_ended: cleaned_reply.is_endcall,
+    })
+}
+
+fn openai_error(err: String) -> (StatusCode, String) {
+    (StatusCode::BadGateway, format!("OpenAI Error: {}", err))
+}
+
+pub async fn handle_session_list_artifacts(_request: Arc<HttpRequest>) -> HttpResponse {
+    match with_database("get_all_sessions", get_all_sessions).await {
         Ok(rows) => {
             let artifacts: Vec<_> = rows
                 .into_iter()
@@ -163,42 +277,39 @@ pub fn handle_session_list_artifacts(_body: &str) -> HttpResponse {
                 .collect();
 
             let json = json!({ "artifacts": artifacts });
-            response_ok(
-                "application/json; charset=utf-8",
-                json.to_string().into_bytes(),
-            )
+            response_json(&json)
         }
-        Err(e) => response_ok(
-            "application/json; charset=utf-8",
-            json!({ "error": format!("Failed to query sessions: {}", e) })
-                .to_string()
-                .into_bytes(),
+        Err(e) => response_error(
+            StatusCode::InternalServerError,
+            &format!("Failed to query sessions: {}", e),
         ),
     }
 }
 
-pub fn handle_session_get_artifact(body: &str) -> HttpResponse {
-    let parsed: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+pub async fn handle_session_get_artifact(request: Arc<HttpRequest>) -> HttpResponse {
+    let parsed: serde_json::Value = serde_json::from_str(request.body_str()).unwrap_or_default();
     let session_id = parsed
         .get("session_id")
         .and_then(|v| v.as_str())
         .unwrap_or("");
 
-    // Connect to database
-    let conn = match crate::sys_db::db_sessions::init_database() {
-        Ok(c) => c,
-        Err(e) => {
-            return response_ok(
-                "application/json; charset=utf-8",
-                json!({ "error": format!("Failed to open database: {}", e) })
-                    .to_string()
-                    .into_bytes(),
-            );
-        }
-    };
+    if session_id.is_empty() {
+        return response_error(StatusCode::BadRequest, "Missing session_id");
+    }
+
+    artifact_response(session_id.to_string()).await
+}
+
+/// GET /api/sessions/{id}
+pub async fn handle_session_artifact_by_id(request: Arc<HttpRequest>) -> HttpResponse {
+    artifact_response(request.param("id").unwrap_or("").to_string()).await
+}
+
+async fn artifact_response(session_id: String) -> HttpResponse {
+    record_session_id(&session_id);
 
     // Fetch session by ID
-    match crate::sys_db::db_sessions::get_session_by_id(&conn, session_id) {
+    match with_database("get_session_by_id", move |conn| get_session_by_id(conn, &session_id)).await {
         Ok(Some(row)) => {
             let json = json!({
                 "sessionId": row.session_id,
@@ -218,26 +329,20 @@ pub fn handle_session_get_artifact(body: &str) -> HttpResponse {
                 }
             });
 
-            response_ok(
-                "application/json; charset=utf-8",
-                json.to_string().into_bytes(),
-            )
+            response_json(&json)
         }
-        Ok(None) => response_not_found("Session not found in database"),
-        Err(e) => response_ok(
-            "application/json; charset=utf-8",
-            json!({ "error": format!("Database error: {}", e) })
-                .to_string()
-                .into_bytes(),
+        Ok(None) => response_error(StatusCode::NotFound, "Session not found in database"),
+        Err(e) => response_error(
+            StatusCode::InternalServerError,
+            &format!("Database error: {}", e),
         ),
     }
 }
 
 // ----- Conversation End / Summary Logic ----- //
 
-pub fn end_convo(sessions: &mut MutexGuard<HashMap<String, Session>>, session: &Session, final_reply: String) -> HttpResponse {
-    println!("========== Conversation End ==========");
-    println!("Session ID: {}", session.session_id);
+pub fn end_convo(sessions: &mut MutexGuard<HashMap<String, Session>>, session: &Session) {
+    info!("Conversation ended, summarising in the background");
 
     // Clone for thread
     let session_clone = session.clone();
@@ -245,39 +350,30 @@ pub fn end_convo(sessions: &mut MutexGuard<HashMap<String, Session>>, session: &
     // Remove session immediately
     sessions.remove(&session.session_id);
 
-    // Spawn background thread for summary + DB save
-    std::thread::spawn(move || {
-        if let Err(e) = spawn_end_convo_async(session_clone) {
-            eprintln!("Async end_convo error: {}", e);
+    // Spawn background task for summary + DB save (shutdown waits for it).
+    // The caller responds immediately (don’t block on summary or DB)
+    spawn_tracked(
+        async move {
+            if let Err(e) = spawn_end_convo_async(session_clone).await {
+                warn!("end_convo failed: {}", e);
+            }
         }
-    });
-
-    // Respond immediately (don’t block on summary or DB)
-    let response_json = json!({
-        "reply": final_reply,
-        "session_ended": true
-    });
-
-    println!("Responding to client (non-blocking summary)...");
-    response_ok(
-        "application/json; charset=utf-8",
-        response_json.to_string().into_bytes(),
-    )
+        .instrument(info_span!("summary_job")),
+    );
 }
 
-fn spawn_end_convo_async(session: Session) -> Result<(), String> {
-    println!(
-        "(Async) Generating session summary for {}",
-        session.session_id
-    );
+async fn spawn_end_convo_async(session: Session) -> Result<(), String> {
+    info!("Generating session summary");
 
     // Generate summary from transcript
-    let summary_json_str = generate_convo_summary(&session.session_chat);
-    let summary = summary_json_str
+    let summary_json_str = generate_convo_summary(&session.session_chat).await;
+    let parsed = summary_json_str
         .as_ref()
-        .and_then(|s| serde_json::from_str::<SessionSummary>(s).ok())
+        .and_then(|s| serde_json::from_str::<SessionSummary>(s).ok());
+    let summarised = parsed.is_some();
+    let summary = parsed
         .unwrap_or_else(|| {
-            eprintln!("(Async) Failed to parse AI summary — using fallback");
+            warn!("Failed to parse AI summary — using fallback");
             SessionSummary {
                 caller_name: "".into(),
                 caller_number: "".into(),
@@ -312,44 +408,45 @@ fn spawn_end_convo_async(session: Session) -> Result<(), String> {
     };
 
     // --- DB Save ---
-    if let Ok(conn) = init_database() {
-        let db_row = SessionRow {
-            session_id: artifact.session_id.clone(),
-            session_transcript: artifact.session_transcript.clone(),
-            session_start: start_str.clone(),
-            session_end: end_str.clone(),
-            caller_name: Some(summary.caller_name.clone()),
-            caller_number: Some(summary.caller_number.clone()),
-            caller_company: Some(summary.company.clone()),
-            summary_solution_type: Some(summary.solution_type.clone()),
-            summary_project_details: Some(summary.project_details.clone()),
-            summary_additional_notes: Some(summary.additional_notes.clone()),
-            summary_tags: Some(summary.tags.join(",")),
-        };
-
-        if let Err(e) = insert_session(&conn, &db_row) {
-            eprintln!("(Async) DB insert failed: {}", e);
-        } else {
-            println!("(Async) Session {} saved to DB", artifact.session_id);
+    let db_row = SessionRow {
+        session_id: artifact.session_id.clone(),
+        session_transcript: artifact.session_transcript.clone(),
+        session_start: start_str.clone(),
+        session_end: end_str.clone(),
+        caller_name: Some(summary.caller_name.clone()),
+        caller_number: Some(summary.caller_number.clone()),
+        caller_company: Some(summary.company.clone()),
+        summary_solution_type: Some(summary.solution_type.clone()),
+        summary_project_details: Some(summary.project_details.clone()),
+        summary_additional_notes: Some(summary.additional_notes.clone()),
+        summary_tags: Some(summary.tags.join(",")),
+    };
+
+    match with_database("insert_session", move |conn| insert_session(conn, &db_row)).await {
+        Ok(()) => {
+            info!("Session saved to DB");
+            SUMMARY_JOBS.inc(&[if summarised { "saved" } else { "saved_without_summary" }]);
+        }
+        Err(e) => {
+            warn!("DB save failed: {}", e);
+            SUMMARY_JOBS.inc(&["db_failed"]);
         }
-    } else {
-        eprintln!("(Async) Failed to open DB connection");
     }
 
     Ok(())
 }
 
-fn generate_convo_summary(session_chat: &str) -> Option<String> {
-    use crate::sys_bot::bot_instructions::get_instructions;
+async fn generate_convo_summary(session_chat: &str) -> Option<String> {
+    use crate::sys_bot::bot_instructions::{SUMMARY_INSTRUCTIONS, get_instructions};
     use crate::sys_bot::bot_openai::ask_openai;
 
-    let instructions = get_instructions("cfg/bots/instructions_summary.txt");
+    let instructions = get_instructions(SUMMARY_INSTRUCTIONS);
     let messages = vec![("system", instructions.as_str()), ("user", session_chat)];
 
-    match ask_openai(messages) {
+    match ask_openai(messages).await {
         Ok(summary) => Some(summary.trim().to_string()),
         Err(err) => {
-            eprintln!("OpenAI Summary Error: {}", err);
+            warn!("OpenAI summary error: {}", err);
             None
         }
     }
@@ -363,6 +460,18 @@ struct InputData {
 }
 
 impl InputData {
+    /// JSON body, or query parameters when there is no body (`EventSource`
+    /// can only send GET requests).
+    fn from_request(request: &HttpRequest) -> Self {
+        if !request.body.is_empty() {
+            return Self::from_json(request.body_str());
+        }
+        InputData {
+            session_id: request.query_param("session_id").unwrap_or_default(),
+            input: request.query_param("input").unwrap_or_default(),
+        }
+    }
+
     fn from_json(body: &str) -> Self {
         let parsed: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
         let session_id = parsed
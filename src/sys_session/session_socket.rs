@@ -0,0 +1,132 @@
+// ----- Imports ----- //
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+
+use crate::{
+    sys_core::{
+        HttpRequest, record_session_id,
+        core_websocket::{
+            CLOSE_NORMAL, CLOSE_POLICY_VIOLATION, CLOSE_UNSUPPORTED_DATA, Incoming, Message, WebSocket,
+        },
+    },
+    sys_session::{session_handlers::process_input, session_state::get_session_manager},
+};
+
+// ----- Constants ----- //
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1); // How often expiry is checked
+const EXPIRY_WARNING_SECS: u64 = 60;
+
+// ----- Socket Handler ----- //
+
+/// WebSocket /api/session/ws/{id}
+///
+/// Client → server: `{"input": "..."}` (a bare text message also works).
+/// Server → client, one JSON object per message, tagged by `type`:
+/// `session`, `bot_reply`, `session_expiring`, `session_expired`,
+/// `session_ended` and `error`.
+pub async fn handle_session_socket(request: Arc<HttpRequest>, mut socket: WebSocket) {
+    serve_session(&request, &mut socket).await;
+    socket.finish().await;
+}
+
+async fn serve_session(request: &HttpRequest, socket: &mut WebSocket) {
+    let session_id = request.param("id").unwrap_or("").to_string();
+    record_session_id(&session_id);
+
+    let Some(session) = get_session_manager().get_session(&session_id) else {
+        send_event(socket, json!({ "type": "error", "error": "Session not found" })).await;
+        socket.close(CLOSE_POLICY_VIOLATION, "Session not found").await;
+        return;
+    };
+
+    send_event(
+        socket,
+        json!({
+            "type": "session",
+            "session_id": session.session_id,
+            "expires_in": session.time_remaining()
+        }),
+    )
+    .await;
+
+    let mut expires_at = session.session_timeout;
+    let mut warned = false;
+
+    loop {
+        match socket.read(POLL_INTERVAL).await {
+            Incoming::Message(Message::Text(text)) => {
+                let input = parse_input(&text);
+                if input.trim().is_empty() {
+                    send_event(socket, json!({ "type": "error", "error": "Missing input" })).await;
+                    continue;
+                }
+
+                match process_input(&session_id, &input).await {
+                    Ok(turn) if turn.session_ended => {
+                        send_event(socket, json!({ "type": "session_ended", "reply": turn.reply })).await;
+                        socket.close(CLOSE_NORMAL, "Session ended").await;
+                        return;
+                    }
+                    Ok(turn) => {
+                        send_event(socket, json!({ "type": "bot_reply", "reply": turn.reply })).await;
+                    }
+                    Err((_, msg)) => {
+                        send_event(socket, json!({ "type": "error", "error": msg })).await;
+                    }
+                }
+            }
+            Incoming::Message(Message::Binary) => {
+                socket.close(CLOSE_UNSUPPORTED_DATA, "Expected a text message").await;
+                return;
+            }
+            Incoming::Idle => {}
+            Incoming::Closed => return,
+        }
+
+        // The tick thread purges expired sessions; REST may also end it
+        match get_session_manager().get_session(&session_id) {
+            Some(session) => {
+                expires_at = session.session_timeout;
+                let remaining = session.time_remaining();
+                if !warned && remaining <= EXPIRY_WARNING_SECS {
+                    warned = true;
+                    send_event(socket, json!({ "type": "session_expiring", "expires_in": remaining })).await;
+                }
+            }
+            None if Instant::now() >= expires_at => {
+                send_event(socket, json!({ "type": "session_expired" })).await;
+                socket.close(CLOSE_NORMAL, "Session expired").await;
+                return;
+            }
+            None => {
+                send_event(socket, json!({ "type": "session_ended" })).await;
+                socket.close(CLOSE_NORMAL, "Session ended").await;
+                return;
+            }
+        }
+    }
+}
+
+// ----- Helpers ----- //
+
+/// Failed sends surface as `Closed` on the next read.
+async fn send_event(socket: &mut WebSocket, event: serde_json::Value) {
+    let _ = socket.send_text(&event.to_string()).await;
+}
+
+fn parse_input(text: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(parsed) => parsed
+            .get("input")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+        Err(_) => text.to_string(),
+    }
+}
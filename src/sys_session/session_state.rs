@@ -7,6 +7,9 @@ use std::{
 };
 
 use uuid::Uuid;
+use tracing::debug;
+
+use crate::sys_core::core_metrics::register_gauge;
 
 // ----- Global Session Management ----- //
 
@@ -16,6 +19,12 @@ pub fn init_session_manager() {
     SESSION_MANAGER
         .set(SessionManager::new())
         .expect("SessionManager already initialized");
+
+    register_gauge(
+        "charmline_active_sessions",
+        "Chat sessions that have not expired or ended.",
+        || get_session_manager().active_count() as f64,
+    );
 }
 
 pub fn get_session_manager() -> &'static SessionManager {
@@ -50,6 +59,17 @@ impl SessionManager {
         session
     }
 
+    /// Sessions not yet expired, whether or not `tick` has purged the rest.
+    pub fn active_count(&self) -> usize {
+        let now = Instant::now();
+        self.sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|s| s.session_timeout > now)
+            .count()
+    }
+
     pub fn get_session(&self, session_id: &str) -> Option<Session> {
         self.sessions.lock().unwrap().get(session_id).cloned()
     }
@@ -66,9 +86,9 @@ impl SessionManager {
         let now = Instant::now();
         map.retain(|_, s| s.session_timeout > now);
         for (id, session) in map.iter() {
-            println!(
-                "Session ID: {}, Expires In: {} secs",
-                id,
+            debug!(
+                session_id = %id,
+                "Expires in {} secs",
                 (session.session_timeout - now).as_secs()
             );
         }
@@ -76,6 +76,7 @@ const previewLog = document.getElementById("preview-log");
 const micBtn = document.getElementById("mic-btn");
 const voicePreview = document.getElementById("voice-preview");
 const statusIndicator = document.getElementById("status-indicator");
+const botName = document.body.dataset.botName || "Bot"; // Set by the page template
 
 function appendMessage(role, text) {
     const bubble = document.createElement("div");
@@ -100,7 +101,7 @@ async function sendInput(text) {
 
     appendMessage("user", text);
     const loader = showBotTyping();
-    voicePreview.textContent = "Bot is thinking...";
+    voicePreview.textContent = `${botName} is thinking...`;
 
     const res = await fetch("/api/session/sendinput", {
         method: "POST",
@@ -124,7 +125,8 @@ async function sendInput(text) {
             flashSentState();
         }
     } else {
-        appendMessage("bot", "(Network error)");
+        const err = await res.json().catch(() => ({}));
+        appendMessage("bot", err.error || "(Network error)");
     }
 
     voicePreview.textContent = "Waiting for voice...";
@@ -244,7 +246,7 @@ if ("webkitSpeechRecognition" in window || "SpeechRecognition" in window) {
             const textToSend = finalTranscript.trim();
             if (!textToSend) return;
 
-            voicePreview.textContent = "Bot is thinking...";
+            voicePreview.textContent = `${botName} is thinking...`;
             finalTranscript = "";
             interimTranscript = "";
             await sendInput(textToSend);
@@ -0,0 +1,18 @@
+#!/usr/bin/env bash
+set -e
+
+# Generates a self-signed certificate for testing the HTTPS listener locally.
+# Enable it in cfg/config.json with: "tls": { "enabled": true }
+
+SCRIPT_DIR="$( cd "$( dirname "${BASH_SOURCE[0]}" )" && pwd )"
+TLS_DIR="$(realpath "$SCRIPT_DIR/..")/cfg/tls"
+
+mkdir -p "$TLS_DIR"
+
+openssl req -x509 -newkey rsa:2048 -nodes -days 365 \
+    -keyout "$TLS_DIR/key.pem" \
+    -out "$TLS_DIR/cert.pem" \
+    -subj "/CN=localhost" \
+    -addext "subjectAltName=DNS:localhost,IP:127.0.0.1"
+
+echo "Wrote $TLS_DIR/cert.pem and $TLS_DIR/key.pem"